@@ -1,7 +1,5 @@
-use std::collections::HashMap;
-
 use ic_pluto::{
-    http::{HttpRequest, HttpResponse},
+    http::{HeaderMap, HttpRequest, HttpResponse},
     router::Router,
 };
 use serde::{Deserialize, Serialize};
@@ -9,7 +7,7 @@ use serde_aux::prelude::deserialize_number_from_string;
 use serde_json::json;
 use validator::Validate;
 
-use crate::utils::map_validation_err;
+use ic_pluto::validation::validation_error;
 
 pub(crate) fn setup() -> Router {
     let mut router = Router::new();
@@ -38,7 +36,7 @@ pub(crate) fn setup() -> Router {
 
         Ok(HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 200,
                 "message": "Hello World from POST",
@@ -67,11 +65,11 @@ pub(crate) fn setup() -> Router {
         }
 
         let my_params: Params = req.params_into_struct()?;
-        my_params.validate().map_err(map_validation_err)?;
+        my_params.validate().map_err(validation_error)?;
 
         Ok(HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 200,
                 "message": "Hello World from GET",
@@ -1,3 +1,2 @@
 mod bootstrap;
 mod controller;
-mod utils;
@@ -1,8 +1,6 @@
-use std::collections::HashMap;
-
 use ic_cdk::println;
 use ic_pluto::{
-    http::{HttpRequest, HttpResponse, HttpServe},
+    http::{HeaderMap, HttpRequest, HttpResponse, HttpServe},
     router::Router,
 };
 use serde_json::json;
@@ -15,7 +13,7 @@ pub(crate) fn setup() -> Router {
 
         Ok(HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 200,
                 "message": "Hello World from PUT",
@@ -29,7 +27,7 @@ pub(crate) fn setup() -> Router {
             .map_err(|_| HttpServe::internal_server_error().unwrap_err());
         Ok(HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 200,
                 "message": "Hello World from POST",
@@ -41,7 +39,7 @@ pub(crate) fn setup() -> Router {
     router.get("/", false, |_req: HttpRequest| async move {
         Ok(HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 200,
                 "message": "Hello World from GET",
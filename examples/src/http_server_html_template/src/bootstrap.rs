@@ -1,10 +1,10 @@
 use ic_cdk::{post_upgrade, query, update};
 use ic_pluto::{
-    http::{HttpResponse, HttpServe, RawHttpRequest, RawHttpResponse},
+    http::{HttpServe, RawHttpRequest, RawHttpResponse},
     http_serve,
     router::Router,
 };
-use std::{cell::RefCell, collections::HashMap};
+use std::cell::RefCell;
 
 use crate::controller;
 
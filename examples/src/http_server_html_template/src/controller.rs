@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use ic_pluto::{http::HttpRequest, render_view, router::Router};
 
 pub(crate) fn setup() -> Router {
@@ -0,0 +1,130 @@
+/// The parsed value of an `Accept` request header.
+///
+/// Every media range is stored with its quality (`q`) value. A media type gets the
+/// quality of the most specific range matching it, so `text/html` takes precedence
+/// over `text/*`, which takes precedence over `*/*`. A quality of `0` explicitly
+/// refuses a media type.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::accept::Accept;
+///
+/// let accept = Accept::parse("text/html, application/json;q=0.9, */*;q=0.1");
+/// assert!(accept.accepts("image/png"));
+/// assert_eq!(accept.preferred(&["application/json", "text/html"]), Some("text/html"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept {
+    ranges: Vec<(String, f32)>,
+}
+
+impl Default for Accept {
+    /// Accept every media type, like a request without an `Accept` header.
+    fn default() -> Self {
+        Self::parse("*/*")
+    }
+}
+
+impl Accept {
+    /// Parse the value of an `Accept` header.
+    /// Parameters other than `q` are ignored, malformed quality values are treated as `1`
+    /// and empty entries are skipped.
+    pub fn parse(header: &str) -> Self {
+        let mut ranges = Vec::new();
+        for item in header.split(',') {
+            let mut parts = item.split(';');
+            let range = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            if range.is_empty() {
+                continue;
+            }
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            ranges.push((range, quality));
+        }
+        Self { ranges }
+    }
+
+    /// Get the quality the client assigned to `mime`, parameters like `charset` are ignored.
+    pub fn quality(&self, mime: &str) -> f32 {
+        let mime = mime.split(';').next().unwrap_or("").trim();
+        let (kind, _) = mime.split_once('/').unwrap_or((mime, ""));
+        let mut best: Option<(u8, f32)> = None;
+        for (range, quality) in self.ranges.iter() {
+            let specificity = if range.eq_ignore_ascii_case(mime) {
+                2
+            } else if range
+                .strip_suffix("/*")
+                .is_some_and(|range_kind| range_kind.eq_ignore_ascii_case(kind))
+            {
+                1
+            } else if range == "*/*" || range == "*" {
+                0
+            } else {
+                continue;
+            };
+            if !matches!(best, Some((best_specificity, _)) if best_specificity >= specificity) {
+                best = Some((specificity, *quality));
+            }
+        }
+        best.map_or(0.0, |(_, quality)| quality)
+    }
+
+    /// Check if the client accepts `mime`.
+    pub fn accepts(&self, mime: &str) -> bool {
+        self.quality(mime) > 0.0
+    }
+
+    /// Pick the media type from `offered` with the highest quality.
+    /// Ties are resolved by the order of `offered`, so it should list the server preferences first.
+    pub fn preferred<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&str, f32)> = None;
+        for mime in offered {
+            let quality = self.quality(mime);
+            if quality > 0.0 && !matches!(best, Some((_, best_quality)) if best_quality >= quality)
+            {
+                best = Some((mime, quality));
+            }
+        }
+        best.map(|(mime, _)| mime)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_specificity() {
+        let accept = Accept::parse("text/*;q=0.5, text/html, */*;q=0.1, image/png;q=0");
+        assert_eq!(accept.quality("text/html; charset=utf-8"), 1.0);
+        assert_eq!(accept.quality("TEXT/CSV"), 0.5);
+        assert_eq!(accept.quality("application/json"), 0.1);
+        assert!(!accept.accepts("image/png"));
+        assert_eq!(Accept::parse("application/json").quality("text/html"), 0.0);
+    }
+
+    #[test]
+    fn test_preferred() {
+        let accept = Accept::parse("application/json;q=0.8, text/html");
+        assert_eq!(
+            accept.preferred(&["application/json", "text/html"]),
+            Some("text/html")
+        );
+        assert_eq!(accept.preferred(&["image/png"]), None);
+
+        let accept = Accept::default();
+        assert_eq!(
+            accept.preferred(&["application/json", "text/html"]),
+            Some("application/json")
+        );
+        let accept = Accept::parse("text/html;level=1;q=invalid, application/json;q=0.9");
+        assert_eq!(
+            accept.preferred(&["application/json", "text/html"]),
+            Some("text/html")
+        );
+    }
+}
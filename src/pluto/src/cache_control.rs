@@ -0,0 +1,173 @@
+use std::fmt;
+
+/// A builder of the `Cache-Control` response header.
+///
+/// Directives are written in a fixed order, so the same config always produces the same header.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::cache_control::CacheControl;
+///
+/// let cache = CacheControl::new()
+///     .public()
+///     .max_age(3600)
+///     .stale_while_revalidate(60)
+///     .stale_if_error(86400);
+/// assert_eq!(
+///     cache.to_string(),
+///     "public, max-age=3600, stale-while-revalidate=60, stale-if-error=86400"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    must_revalidate: bool,
+    immutable: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    stale_while_revalidate: Option<u64>,
+    stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    /// Create an empty `Cache-Control` header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes the builder, allow shared caches to store the response and returns changed builder
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self.private = false;
+        self
+    }
+
+    /// Consumes the builder, restrict caching to the client and returns changed builder
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self.public = false;
+        self
+    }
+
+    /// Consumes the builder, require revalidation before every reuse and returns changed builder
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Consumes the builder, forbid storing the response and returns changed builder
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Consumes the builder, forbid serving the response stale without revalidation and returns changed builder
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Consumes the builder, mark the response as never changing and returns changed builder
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Consumes the builder, set for how many seconds the response is fresh and returns changed builder
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Consumes the builder, set the freshness for shared caches and returns changed builder
+    pub fn s_maxage(mut self, seconds: u64) -> Self {
+        self.s_maxage = Some(seconds);
+        self
+    }
+
+    /// Consumes the builder, set for how many seconds a stale response may be served
+    /// while it is revalidated in the background and returns changed builder
+    pub fn stale_while_revalidate(mut self, seconds: u64) -> Self {
+        self.stale_while_revalidate = Some(seconds);
+        self
+    }
+
+    /// Consumes the builder, set for how many seconds a stale response may be served
+    /// when revalidation fails and returns changed builder
+    pub fn stale_if_error(mut self, seconds: u64) -> Self {
+        self.stale_if_error = Some(seconds);
+        self
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.immutable, "immutable"),
+        ];
+        let durations = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+            (self.stale_if_error, "stale-if-error"),
+        ];
+        let directives: Vec<String> = flags
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, name)| name.to_string())
+            .chain(durations.iter().filter_map(|(seconds, name)| {
+                seconds.map(|seconds| format!("{}={}", name, seconds))
+            }))
+            .collect();
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stale_directives() {
+        let cache = CacheControl::new()
+            .public()
+            .max_age(600)
+            .stale_while_revalidate(30)
+            .stale_if_error(3600);
+        assert_eq!(
+            cache.to_string(),
+            "public, max-age=600, stale-while-revalidate=30, stale-if-error=3600"
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(CacheControl::new().to_string(), "");
+        assert_eq!(
+            CacheControl::new()
+                .public()
+                .private()
+                .no_cache()
+                .to_string(),
+            "private, no-cache"
+        );
+        assert_eq!(
+            CacheControl::new()
+                .no_store()
+                .must_revalidate()
+                .immutable()
+                .s_maxage(5)
+                .to_string(),
+            "no-store, must-revalidate, immutable, s-maxage=5"
+        );
+    }
+}
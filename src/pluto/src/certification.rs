@@ -0,0 +1,494 @@
+use std::{cell::RefCell, collections::BTreeMap};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::http::{HttpResponse, RawHttpResponse};
+
+type Hash = [u8; 32];
+
+const EXPRESSION_HEADER: &str = "IC-CertificateExpression";
+const CERTIFICATE_HEADER: &str = "IC-Certificate";
+const STATUS_PSEUDO_HEADER: &str = ":ic-cert-status";
+/// Headers `HttpServe` may set differently for every request, so they are never certified
+const UNCERTIFIED_HEADERS: [&str; 1] = ["x-powered-by"];
+/// The CBOR tag marking self-described CBOR data
+const CBOR_SELF_DESCRIBE: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+thread_local! {
+    static CERTIFIED: RefCell<Certified> = RefCell::new(Certified::default());
+}
+
+/// A labeled hash tree, as specified by the IC interface specification.
+enum HashTree {
+    Empty,
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned(Hash),
+}
+
+impl HashTree {
+    fn digest(&self) -> Hash {
+        match self {
+            HashTree::Empty => hash_with_domain("ic-hashtree-empty", &[]),
+            HashTree::Fork(left, right) => {
+                hash_with_domain("ic-hashtree-fork", &[&left.digest(), &right.digest()])
+            }
+            HashTree::Labeled(label, tree) => {
+                hash_with_domain("ic-hashtree-labeled", &[label, &tree.digest()])
+            }
+            HashTree::Leaf(value) => hash_with_domain("ic-hashtree-leaf", &[value]),
+            HashTree::Pruned(hash) => *hash,
+        }
+    }
+
+    fn write_cbor(&self, out: &mut Vec<u8>) {
+        match self {
+            HashTree::Empty => {
+                cbor_head(out, 4, 1);
+                cbor_head(out, 0, 0);
+            }
+            HashTree::Fork(left, right) => {
+                cbor_head(out, 4, 3);
+                cbor_head(out, 0, 1);
+                left.write_cbor(out);
+                right.write_cbor(out);
+            }
+            HashTree::Labeled(label, tree) => {
+                cbor_head(out, 4, 3);
+                cbor_head(out, 0, 2);
+                cbor_bytes(out, label);
+                tree.write_cbor(out);
+            }
+            HashTree::Leaf(value) => {
+                cbor_head(out, 4, 2);
+                cbor_head(out, 0, 3);
+                cbor_bytes(out, value);
+            }
+            HashTree::Pruned(hash) => {
+                cbor_head(out, 4, 2);
+                cbor_head(out, 0, 4);
+                cbor_bytes(out, hash);
+            }
+        }
+    }
+}
+
+/// A node of the certification tree, children are kept sorted by label.
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<Vec<u8>, Node>,
+    leaf: bool,
+}
+
+impl Node {
+    fn insert(&mut self, path: &[Vec<u8>]) {
+        match path.split_first() {
+            Some((label, rest)) => self.children.entry(label.clone()).or_default().insert(rest),
+            None => self.leaf = true,
+        }
+    }
+
+    /// Build the hash tree of the node, pruning every branch off `path` when it is given.
+    fn tree(&self, path: Option<&[Vec<u8>]>) -> HashTree {
+        if self.leaf {
+            return HashTree::Leaf(Vec::new());
+        }
+        let children: Vec<HashTree> = self
+            .children
+            .iter()
+            .map(|(label, child)| match path {
+                None => HashTree::Labeled(label.clone(), Box::new(child.tree(None))),
+                Some([first, rest @ ..]) if first == label => {
+                    HashTree::Labeled(label.clone(), Box::new(child.tree(Some(rest))))
+                }
+                Some(_) => HashTree::Pruned(
+                    HashTree::Labeled(label.clone(), Box::new(child.tree(None))).digest(),
+                ),
+            })
+            .collect();
+        fork(children)
+    }
+}
+
+fn fork(mut trees: Vec<HashTree>) -> HashTree {
+    match trees.len() {
+        0 => HashTree::Empty,
+        1 => trees.remove(0),
+        len => {
+            let right = trees.split_off(len / 2);
+            HashTree::Fork(Box::new(fork(trees)), Box::new(fork(right)))
+        }
+    }
+}
+
+/// A response certified for a path, with the names of its certified headers.
+struct CertifiedResponse {
+    expression: String,
+    headers: Vec<String>,
+    response_hash: Hash,
+}
+
+#[derive(Default)]
+struct Certified {
+    tree: Node,
+    responses: BTreeMap<String, Vec<CertifiedResponse>>,
+}
+
+/// Certify `res` as the response to GET requests for `path`, following the HTTP response verification v2.
+///
+/// The status code, the body and every header except `X-Powered-By` are certified, and the certified data
+/// of the canister is set to the root hash of all certified responses. A path may have several certified
+/// responses, e.g. one for each content encoding of a static file.
+/// `use_static_files!` certifies every static file on its own.
+///
+/// `HttpServe` attaches the `IC-Certificate` and `IC-CertificateExpression` headers to query responses
+/// equal to a certified one, so the gateway can trust them without an update call.
+/// Responses changed by the response plugins (e.g. compression or CORS headers) are served uncertified.
+///
+/// Changing the certified data is allowed only in `init`, `post_upgrade` and update calls.
+/// The certified data of the canister is owned by this module, so it should not be set by other code.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[post_upgrade]
+/// fn post_upgrade() {
+///     pluto::certification::certify("/robots.txt", &HttpResponse::builder().text(ROBOTS.to_string()).build());
+/// }
+/// ```
+pub fn certify(path: &str, res: &HttpResponse) {
+    let res: RawHttpResponse = res.clone().into();
+    let mut headers: Vec<String> = Vec::new();
+    for (name, _) in res.headers.iter() {
+        let name = name.to_ascii_lowercase();
+        if !UNCERTIFIED_HEADERS.contains(&name.as_str()) && !headers.contains(&name) {
+            headers.push(name);
+        }
+    }
+    let expression = expression(&headers);
+    let response_hash = response_hash(&res, &headers, &expression);
+
+    CERTIFIED.with(|certified| {
+        let mut certified = certified.borrow_mut();
+        certified
+            .tree
+            .insert(&tree_path(path, &expression, &response_hash));
+        let responses = certified.responses.entry(path.to_string()).or_default();
+        if !responses
+            .iter()
+            .any(|certified| certified.response_hash == response_hash)
+        {
+            responses.push(CertifiedResponse {
+                expression,
+                headers,
+                response_hash,
+            });
+        }
+        set_certified_data(&certified.tree.tree(None).digest());
+    });
+}
+
+/// Remove all certified responses, e.g. before certifying the new content in `post_upgrade`.
+pub fn clear() {
+    CERTIFIED.with(|certified| {
+        *certified.borrow_mut() = Certified::default();
+    });
+    set_certified_data(&HashTree::Empty.digest());
+}
+
+/// Attach the certificate headers to the response of a query call for `path`, if it is certified.
+pub(crate) fn attach(path: &str, res: &mut RawHttpResponse) {
+    if let Some(certificate) = data_certificate() {
+        attach_with(path, res, &certificate);
+    }
+}
+
+fn attach_with(path: &str, res: &mut RawHttpResponse, certificate: &[u8]) {
+    CERTIFIED.with(|certified| {
+        let certified = certified.borrow();
+        let responses = match certified.responses.get(path) {
+            Some(responses) => responses,
+            None => return,
+        };
+        let certified_response = responses.iter().find(|certified| {
+            response_hash(res, &certified.headers, &certified.expression) == certified.response_hash
+        });
+        let certified_response = match certified_response {
+            Some(certified_response) => certified_response,
+            None => return,
+        };
+
+        let tree_path = tree_path(
+            path,
+            &certified_response.expression,
+            &certified_response.response_hash,
+        );
+        let mut tree = CBOR_SELF_DESCRIBE.to_vec();
+        certified.tree.tree(Some(&tree_path)).write_cbor(&mut tree);
+        let mut expr_path = CBOR_SELF_DESCRIBE.to_vec();
+        let labels = expr_path_labels(path);
+        cbor_head(&mut expr_path, 4, labels.len() as u64);
+        for label in labels.iter() {
+            cbor_head(&mut expr_path, 3, label.len() as u64);
+            expr_path.extend_from_slice(label);
+        }
+
+        res.headers.insert(
+            EXPRESSION_HEADER.to_string(),
+            certified_response.expression.clone(),
+        );
+        res.headers.insert(
+            CERTIFICATE_HEADER.to_string(),
+            format!(
+                "certificate=:{}:, tree=:{}:, expr_path=:{}:, version=2",
+                BASE64.encode(certificate),
+                BASE64.encode(tree),
+                BASE64.encode(expr_path)
+            ),
+        );
+    });
+}
+
+/// The CEL expression certifying the response with `headers`, without certifying the request.
+fn expression(headers: &[String]) -> String {
+    let headers: Vec<String> = headers
+        .iter()
+        .map(|header| format!("\"{}\"", header))
+        .collect();
+    format!(
+        "default_certification(ValidationArgs{{certification:Certification{{no_request_certification:Empty{{}},\
+         response_certification:ResponseCertification{{certified_response_headers:ResponseHeaderList{{\
+         headers:[{}]}}}}}}}})",
+        headers.join(",")
+    )
+}
+
+/// Hash the status code, the certified headers (with the expression header) and the body of `res`.
+fn response_hash(res: &RawHttpResponse, headers: &[String], expression: &str) -> Hash {
+    let mut pairs: Vec<Vec<u8>> = res
+        .headers
+        .iter()
+        .filter(|(name, _)| {
+            headers.contains(&name.to_ascii_lowercase())
+                && !name.eq_ignore_ascii_case(EXPRESSION_HEADER)
+        })
+        .map(|(name, value)| {
+            [
+                sha256(name.to_ascii_lowercase().as_bytes()),
+                sha256(value.as_bytes()),
+            ]
+            .concat()
+        })
+        .collect();
+    pairs.push(
+        [
+            sha256(EXPRESSION_HEADER.to_ascii_lowercase().as_bytes()),
+            sha256(expression.as_bytes()),
+        ]
+        .concat(),
+    );
+    pairs.push(
+        [
+            sha256(STATUS_PSEUDO_HEADER.as_bytes()),
+            sha256(&leb128(res.status_code as u64)),
+        ]
+        .concat(),
+    );
+    // The representation-independent hash of the headers
+    pairs.sort();
+    let headers_hash = sha256(&pairs.concat());
+    sha256(&[headers_hash, sha256(&res.body)].concat())
+}
+
+/// The labels of the exact match expression path of `path`, e.g. `http_expr/app.js/<$>` for `/app.js`.
+fn expr_path_labels(path: &str) -> Vec<Vec<u8>> {
+    let mut labels = vec![b"http_expr".to_vec()];
+    labels.extend(
+        path.split('/')
+            .skip(1)
+            .map(|segment| segment.as_bytes().to_vec()),
+    );
+    labels.push(b"<$>".to_vec());
+    labels
+}
+
+fn tree_path(path: &str, expression: &str, response_hash: &Hash) -> Vec<Vec<u8>> {
+    let mut labels = expr_path_labels(path);
+    labels.push(sha256(expression.as_bytes()).to_vec());
+    // The request is not certified
+    labels.push(Vec::new());
+    labels.push(response_hash.to_vec());
+    labels
+}
+
+fn sha256(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+fn hash_with_domain(domain: &str, parts: &[&[u8]]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([domain.len() as u8]);
+    hasher.update(domain.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn leb128(mut value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend([major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((value as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    cbor_head(out, 2, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn set_certified_data(hash: &Hash) {
+    ic_cdk::api::set_certified_data(hash);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn set_certified_data(_hash: &Hash) {}
+
+#[cfg(target_arch = "wasm32")]
+fn data_certificate() -> Option<Vec<u8>> {
+    ic_cdk::api::data_certificate()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn data_certificate() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labeled(label: &str, tree: HashTree) -> HashTree {
+        HashTree::Labeled(label.as_bytes().to_vec(), Box::new(tree))
+    }
+
+    fn leaf(value: &str) -> HashTree {
+        HashTree::Leaf(value.as_bytes().to_vec())
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_hash_tree_digest() {
+        // The example tree of the IC interface specification
+        let tree = HashTree::Fork(
+            Box::new(HashTree::Fork(
+                Box::new(labeled(
+                    "a",
+                    HashTree::Fork(
+                        Box::new(HashTree::Fork(
+                            Box::new(labeled("x", leaf("hello"))),
+                            Box::new(HashTree::Empty),
+                        )),
+                        Box::new(labeled("y", leaf("world"))),
+                    ),
+                )),
+                Box::new(labeled("b", leaf("good"))),
+            )),
+            Box::new(HashTree::Fork(
+                Box::new(labeled("c", HashTree::Empty)),
+                Box::new(labeled("d", leaf("morning"))),
+            )),
+        );
+        assert_eq!(
+            hex(&tree.digest()),
+            "eb5c5b2195e62d996b84c9bcc8259d19a83786a2f59e0878cec84c811f669aa0"
+        );
+    }
+
+    #[test]
+    fn test_witness() {
+        let mut node = Node::default();
+        let paths: Vec<Vec<Vec<u8>>> = ["/", "/app.js", "/app.css", "/img/logo.png"]
+            .iter()
+            .map(|path| tree_path(path, "expr", &sha256(path.as_bytes())))
+            .collect();
+        for path in paths.iter() {
+            node.insert(path);
+        }
+        let root = node.tree(None).digest();
+        for path in paths.iter() {
+            assert_eq!(node.tree(Some(path)).digest(), root);
+        }
+
+        let mut cbor = Vec::new();
+        labeled("a", leaf("b")).write_cbor(&mut cbor);
+        assert_eq!(cbor, [0x83, 0x02, 0x41, b'a', 0x82, 0x03, 0x41, b'b']);
+    }
+
+    #[test]
+    fn test_certify_and_attach() {
+        let response = || {
+            let mut res = HttpResponse::css("body {}".to_string());
+            res.add_raw_header("Cache-Control", "max-age=60".to_string());
+            res
+        };
+        certify("/app.css", &response());
+
+        let mut res: RawHttpResponse = response().into();
+        res.headers
+            .insert("Access-Control-Allow-Origin".to_string(), "*".to_string());
+        attach_with("/app.css", &mut res, b"certificate");
+        let expression = res.headers.get(EXPRESSION_HEADER).unwrap();
+        assert!(expression.contains(r#"headers:["content-type","cache-control"]"#));
+        let header = res.headers.get(CERTIFICATE_HEADER).unwrap();
+        assert!(header.starts_with("certificate=:Y2VydGlmaWNhdGU=:, tree=:2dn3"));
+        assert!(header.ends_with(":, version=2"));
+
+        // A changed body or a path without certified responses get no certificate
+        let mut changed = response();
+        changed.body = "body { color: red }".to_string().into();
+        let mut res: RawHttpResponse = changed.into();
+        attach_with("/app.css", &mut res, b"certificate");
+        assert_eq!(res.headers.get(CERTIFICATE_HEADER), None);
+        let mut res: RawHttpResponse = response().into();
+        attach_with("/other.css", &mut res, b"certificate");
+        assert_eq!(res.headers.get(CERTIFICATE_HEADER), None);
+
+        clear();
+        let mut res: RawHttpResponse = response().into();
+        attach_with("/app.css", &mut res, b"certificate");
+        assert_eq!(res.headers.get(CERTIFICATE_HEADER), None);
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::encoding::AcceptEncoding;
+use crate::http::{HttpBody, HttpRequest, HttpResponse};
+
+/// Configuration of the gzip response compression.
+///
+/// The `level` trades instruction cycles for response size: `1` is the fastest,
+/// `9` gives the smallest output and `0` only wraps the body in the gzip format.
+/// Low levels suit update calls, high levels suit rarely changing assets.
+/// Bodies smaller than `min_size` are sent uncompressed, since the gzip overhead outweighs the gain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionConfig {
+    level: u32,
+    min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: 6,
+            min_size: 0,
+        }
+    }
+}
+
+/// Content types whose bodies are already compressed and do not shrink any further.
+const COMPRESSED_TYPES: [&str; 14] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-7z-compressed",
+    "application/zstd",
+    "font/woff",
+    "font/woff2",
+];
+
+impl CompressionConfig {
+    /// Create a compression config with the default level `6` and no minimum body size.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes the config, set the compression level (clamped to `0..=9`) and returns changed config
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Get the configured compression level.
+    pub fn get_level(&self) -> u32 {
+        self.level
+    }
+
+    /// Consumes the config, set the minimum body size in bytes to compress and returns changed config
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Get the configured minimum body size.
+    pub fn get_min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// Compress `data` with gzip at the configured level.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::new(self.level));
+        // Writing into a `Vec` can not fail
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Compress the body of the response if the client accepts gzip.
+    /// Empty bodies, bodies under `min_size`, streamed bodies, bodies that already have a `Content-Encoding`,
+    /// partial content (`206`, `416` and any response with a `Content-Range` describing the raw bytes)
+    /// and already compressed content types (images, archives, fonts, audio and video) are left untouched.
+    /// A strong `ETag` of a compressed response is turned into a weak one (`W/"…"`).
+    pub fn apply(&self, req: &HttpRequest, res: &mut HttpResponse) {
+        let accept = AcceptEncoding::parse(req.header("Accept-Encoding").unwrap_or(""));
+        if !accept.accepts("gzip") {
+            return;
+        }
+        let encoded = res
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case("Content-Encoding"));
+        let streamed = matches!(res.body, HttpBody::Stream(_));
+        let partial =
+            matches!(res.status_code, 206 | 416) || res.headers.contains_key("Content-Range");
+        if encoded || streamed || partial || Self::is_compressed_type(res) {
+            return;
+        }
+        let body: Vec<u8> = std::mem::replace(&mut res.body, HttpBody::Raw(Vec::new())).into();
+        if body.is_empty() || body.len() < self.min_size {
+            res.body = HttpBody::Raw(body);
+            return;
+        }
+        res.body = HttpBody::Raw(self.compress(&body));
+        res.add_raw_header("Content-Encoding", "gzip".to_string());
+        // A strong tag computed on the identity bytes must not validate the gzipped ones
+        if let Some(etag) = res
+            .headers
+            .get("ETag")
+            .filter(|etag| !etag.starts_with("W/"))
+        {
+            let weak = format!("W/{}", etag);
+            res.add_raw_header("ETag", weak);
+        }
+        // Keep the other `Vary` values, e.g. the `Origin` added by CORS
+        let varies = res.headers.get_all("Vary").any(|vary| {
+            vary.split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("Accept-Encoding"))
+        });
+        if !varies {
+            res.append_header("Vary", "Accept-Encoding".to_string());
+        }
+    }
+
+    fn is_compressed_type(res: &HttpResponse) -> bool {
+        let content_type = match res.headers.get("Content-Type") {
+            Some(content_type) => content_type,
+            None => return false,
+        };
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        media_type.starts_with("audio/")
+            || media_type.starts_with("video/")
+            || COMPRESSED_TYPES
+                .iter()
+                .any(|compressed| media_type.eq_ignore_ascii_case(compressed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{HeaderMap, RawHttpRequest};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn request(accept: &str) -> HttpRequest {
+        RawHttpRequest::test("GET", "/", &[("Accept-Encoding", accept)], &[]).into()
+    }
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_levels() {
+        let data = "pluto ".repeat(1000).into_bytes();
+        let fast = CompressionConfig::new().level(1).compress(&data);
+        let best = CompressionConfig::new().level(9).compress(&data);
+        let stored = CompressionConfig::new().level(0).compress(&data);
+
+        assert_eq!(decompress(&fast), data);
+        assert_eq!(decompress(&best), data);
+        assert_eq!(decompress(&stored), data);
+        assert!(best.len() <= fast.len());
+        assert!(fast.len() < stored.len());
+        assert_eq!(CompressionConfig::new().level(42).get_level(), 9);
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = CompressionConfig::new().level(9);
+        let body = "<h1>Hello</h1>".repeat(10);
+        let response = || HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: body.clone().into(),
+        };
+
+        let mut res = response();
+        config.apply(&request("gzip, br"), &mut res);
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(decompress(&Vec::<u8>::from(res.body)), body.as_bytes());
+
+        let mut res = response();
+        config.apply(&request("br"), &mut res);
+        assert!(!res.headers.contains_key("Content-Encoding"));
+        assert_eq!(res.body, HttpBody::String(body.clone()));
+    }
+
+    #[test]
+    fn test_skipped_bodies() {
+        let config = CompressionConfig::new().min_size(200);
+        let mut res = HttpResponse::css("body {}".to_string());
+        config.apply(&request("gzip"), &mut res);
+        assert!(!res.headers.contains_key("Content-Encoding"));
+        assert_eq!(res.body, HttpBody::Raw(b"body {}".to_vec()));
+
+        let png = vec![0x89; 512];
+        let mut res = HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([("Content-Type".to_string(), "image/png".to_string())]),
+            body: HttpBody::Raw(png.clone()),
+        };
+        config.apply(&request("gzip"), &mut res);
+        assert!(!res.headers.contains_key("Content-Encoding"));
+        assert_eq!(res.body, HttpBody::Raw(png));
+
+        let mut res = HttpResponse::css("body {}".repeat(50));
+        config.apply(&request("gzip"), &mut res);
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// Characters not allowed in a cookie value (RFC 6265), plus `%` to keep the encoding reversible.
+const COOKIE_VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b',')
+    .add(b';')
+    .add(b'\\')
+    .add(b'%');
+
+/// Characters not allowed in a cookie name, a RFC 2616 token, plus `%`.
+const COOKIE_NAME: &AsciiSet = &COOKIE_VALUE
+    .add(b'(')
+    .add(b')')
+    .add(b'<')
+    .add(b'>')
+    .add(b'@')
+    .add(b':')
+    .add(b'/')
+    .add(b'[')
+    .add(b']')
+    .add(b'?')
+    .add(b'=')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters not allowed in the `Path` and `Domain` attributes (RFC 6265).
+const COOKIE_ATTRIBUTE: &AsciiSet = &CONTROLS.add(b';');
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+/// A builder of the `Set-Cookie` response header, see `HttpResponse::set_cookie`.
+///
+/// The value is percent-encoded, `HttpRequest::cookie` decodes it back.
+/// The name, path and domain are percent-encoded only where they contain characters
+/// that could end the attribute or the header, such as `;`, CR and LF.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::cookie::{Cookie, SameSite};
+///
+/// let cookie = Cookie::new("session", "abc123")
+///     .path("/")
+///     .max_age(3600)
+///     .http_only(true)
+///     .secure(true)
+///     .same_site(SameSite::Lax);
+/// assert_eq!(
+///     cookie.to_string(),
+///     "session=abc123; Path=/; Max-Age=3600; HttpOnly; Secure; SameSite=Lax"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<u64>,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Create a session cookie without attributes.
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    /// Consumes the builder, set the name of the cookie and returns changed builder
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Consumes the builder, set the value of the cookie and returns changed builder
+    pub fn value(mut self, value: &str) -> Self {
+        self.value = value.to_string();
+        self
+    }
+
+    /// Consumes the builder, set the path the cookie is sent for and returns changed builder
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Consumes the builder, set the domain the cookie is sent to and returns changed builder
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Consumes the builder, set for how many seconds the cookie is kept and returns changed builder.
+    /// `0` makes the client delete the cookie.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Consumes the builder, set if the cookie is hidden from scripts and returns changed builder
+    pub fn http_only(mut self, enabled: bool) -> Self {
+        self.http_only = enabled;
+        self
+    }
+
+    /// Consumes the builder, set if the cookie is sent only over HTTPS and returns changed builder
+    pub fn secure(mut self, enabled: bool) -> Self {
+        self.secure = enabled;
+        self
+    }
+
+    /// Consumes the builder, set the cross-site policy of the cookie and returns changed builder
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}={}",
+            utf8_percent_encode(&self.name, COOKIE_NAME),
+            utf8_percent_encode(&self.value, COOKIE_VALUE)
+        )?;
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", utf8_percent_encode(path, COOKIE_ATTRIBUTE))?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(
+                f,
+                "; Domain={}",
+                utf8_percent_encode(domain, COOKIE_ATTRIBUTE)
+            )?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Cookie::new("id", "1").to_string(), "id=1");
+        let cookie = Cookie::new("tmp", "")
+            .name("session")
+            .value("a b;c")
+            .domain("example.com")
+            .max_age(0)
+            .same_site(SameSite::None)
+            .secure(true);
+        assert_eq!(
+            cookie.to_string(),
+            "session=a%20b%3Bc; Domain=example.com; Max-Age=0; Secure; SameSite=None"
+        );
+    }
+
+    #[test]
+    fn test_header_injection() {
+        let cookie = Cookie::new("id=1; Domain=evil.com", "1")
+            .path("/\r\nSet-Cookie: admin=1")
+            .domain("example.com; Secure");
+        assert_eq!(
+            cookie.to_string(),
+            "id%3D1%3B%20Domain%3Devil.com=1; Path=/%0D%0ASet-Cookie: admin=1; Domain=example.com%3B Secure"
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{all_or_some::AllOrSome, http::HttpResponse, method::Method};
 use std::ops::Deref;
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Cors {
-    allow_origin: Option<AllOrSome<String>>,
+    allow_origin: Option<AllOrSome<Vec<String>>>,
     allow_methods: Vec<Method>,
     allow_headers: Vec<String>,
     allow_credentials: bool,
@@ -28,7 +28,16 @@ impl Cors {
 
     /// Consumes the `Response` and return an altered response with origin and `vary_origin` set
     pub fn allow_origin(mut self, origin: &str) -> Self {
-        self.allow_origin = Some(AllOrSome::Some(origin.to_string()));
+        self.allow_origin = Some(AllOrSome::Some(vec![origin.to_string()]));
+        self
+    }
+
+    /// Consumes the CORS, set allowed origins to passed origins and returns changed CORS.
+    /// The `Origin` of the request is reflected if it is one of them, together with `Vary: Origin`.
+    pub fn allow_origins(mut self, origins: Vec<&str>) -> Self {
+        self.allow_origin = Some(AllOrSome::Some(
+            origins.iter().map(|s| (*s).to_string()).collect(),
+        ));
         self
     }
 
@@ -38,12 +47,21 @@ impl Cors {
         self
     }
 
-    /// Consumes the Response and set credentials
+    /// Consumes the Response and set credentials.
+    /// Credentials can not be combined with `Access-Control-Allow-Origin: *`,
+    /// so with `any` the origin of the request is reflected instead.
     pub fn credentials(mut self, value: bool) -> Self {
         self.allow_credentials = value;
         self
     }
 
+    /// Consumes the CORS, set vary_origin to passed value and returns changed CORS.
+    /// Enable it when the allowed origin is not "*", so caches keep a response per `Origin`.
+    pub fn vary_origin(mut self, value: bool) -> Self {
+        self.vary_origin = value;
+        self
+    }
+
     /// Consumes the CORS, set expose_headers to
     /// passed headers and returns changed CORS
     pub fn exposed_headers(mut self, headers: Vec<&str>) -> Self {
@@ -76,6 +94,14 @@ impl Cors {
     ///
     /// This will overwrite any existing CORS headers
     pub fn merge(&self, response: &mut HttpResponse) {
+        self.merge_for_origin(None, response)
+    }
+
+    /// Merge CORS headers for a request with the `request_origin` `Origin` header.
+    ///
+    /// With several allowed origins, or any origin with credentials, the matching request origin
+    /// is reflected, other requests get no CORS headers.
+    pub fn merge_for_origin(&self, request_origin: Option<&str>, response: &mut HttpResponse) {
         let origin = match self.allow_origin {
             None => {
                 // This is not a CORS response
@@ -84,9 +110,30 @@ impl Cors {
             Some(ref origin) => origin,
         };
 
+        // Browsers reject credentialed responses allowing any origin with "*", so the origin is reflected instead
+        let reflected = match *origin {
+            AllOrSome::All => self.allow_credentials,
+            AllOrSome::Some(ref origins) => origins.len() != 1,
+        };
+        if reflected {
+            // The allowed origin depends on the request, so caches must keep a response per origin
+            response.append_header("Vary", "Origin".to_string());
+        }
         let origin = match *origin {
-            AllOrSome::All => "*".to_string(),
-            AllOrSome::Some(ref origin) => origin.to_string(),
+            AllOrSome::All if !reflected => "*".to_string(),
+            AllOrSome::Some(ref origins) if !reflected => origins[0].clone(),
+            AllOrSome::All => match request_origin {
+                Some(request_origin) => request_origin.to_string(),
+                None => return,
+            },
+            AllOrSome::Some(ref origins) => match request_origin.filter(|request_origin| {
+                origins
+                    .iter()
+                    .any(|origin| origin.eq_ignore_ascii_case(request_origin))
+            }) {
+                Some(request_origin) => request_origin.to_string(),
+                None => return,
+            },
         };
 
         response.add_raw_header("Access-Control-Allow-Origin", origin);
@@ -129,8 +176,8 @@ impl Cors {
             response.add_raw_header("Access-Control-Max-Age", max_age.to_string());
         }
 
-        if self.vary_origin {
-            response.add_raw_header("Vary", "Origin".to_string());
+        if self.vary_origin && !reflected {
+            response.append_header("Vary", "Origin".to_string());
         }
     }
 }
@@ -0,0 +1,142 @@
+/// The parsed value of an `Accept-Encoding` request header.
+///
+/// Every content coding is stored with its quality (`q`) value, so features that
+/// pick a response encoding (static file sidecars, compression) resolve the client
+/// preferences the same way. A quality of `0` explicitly refuses a coding, even if
+/// the `*` wildcard would otherwise accept it.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::encoding::AcceptEncoding;
+///
+/// let accept = AcceptEncoding::parse("gzip;q=0, *");
+/// assert!(!accept.accepts("gzip"));
+/// assert!(accept.accepts("br"));
+/// assert_eq!(accept.preferred(&["gzip", "br"]), Some("br"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptEncoding {
+    codings: Vec<(String, f32)>,
+}
+
+impl AcceptEncoding {
+    /// Parse the value of an `Accept-Encoding` header.
+    /// Malformed quality values are treated as `1`, empty entries are skipped.
+    pub fn parse(header: &str) -> Self {
+        let mut codings = Vec::new();
+        for item in header.split(',') {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                continue;
+            }
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            codings.push((coding, quality));
+        }
+        Self { codings }
+    }
+
+    /// Get the quality the client assigned to `coding`.
+    ///
+    /// Explicitly listed codings take precedence over the `*` wildcard.
+    /// `identity` is acceptable unless it is refused explicitly or through `*;q=0`.
+    pub fn quality(&self, coding: &str) -> f32 {
+        let lookup = |name: &str| {
+            self.codings
+                .iter()
+                .find(|(listed, _)| listed.eq_ignore_ascii_case(name))
+                .map(|(_, quality)| *quality)
+        };
+        match lookup(coding).or_else(|| lookup("*")) {
+            Some(quality) => quality,
+            None if coding.eq_ignore_ascii_case("identity") => 1.0,
+            None => 0.0,
+        }
+    }
+
+    /// Check if the client accepts `coding`.
+    pub fn accepts(&self, coding: &str) -> bool {
+        self.quality(coding) > 0.0
+    }
+
+    /// Get the acceptable codings listed by the client, ordered from the most to the least preferred.
+    /// Codings with equal quality keep the order of the header.
+    pub fn preferences(&self) -> Vec<&str> {
+        let mut accepted: Vec<&(String, f32)> = self
+            .codings
+            .iter()
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        accepted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        accepted
+            .into_iter()
+            .map(|(coding, _)| coding.as_str())
+            .collect()
+    }
+
+    /// Pick the coding from `available` with the highest quality.
+    /// Ties are resolved by the order of `available`, so it should list the server preferences first.
+    pub fn preferred<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&str, f32)> = None;
+        for coding in available {
+            let quality = self.quality(coding);
+            if quality > 0.0 && !matches!(best, Some((_, best_quality)) if best_quality >= quality)
+            {
+                best = Some((coding, quality));
+            }
+        }
+        best.map(|(coding, _)| coding)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_preferences() {
+        let accept = AcceptEncoding::parse("gzip, deflate, br");
+        assert_eq!(accept.preferences(), vec!["gzip", "deflate", "br"]);
+
+        let accept = AcceptEncoding::parse("deflate;q=0.5, GZIP;q=0.8, br;q=1.0, identity;q=0");
+        assert_eq!(accept.preferences(), vec!["br", "gzip", "deflate"]);
+        assert!(!accept.accepts("identity"));
+        assert!(!accept.accepts("zstd"));
+    }
+
+    #[test]
+    fn test_explicit_refusal_with_wildcard() {
+        let accept = AcceptEncoding::parse("gzip;q=0, *");
+        assert!(!accept.accepts("gzip"));
+        assert!(accept.accepts("br"));
+        assert!(accept.accepts("identity"));
+        assert_eq!(accept.preferences(), vec!["*"]);
+        assert_eq!(accept.preferred(&["gzip"]), None);
+        assert_eq!(accept.preferred(&["gzip", "br"]), Some("br"));
+    }
+
+    #[test]
+    fn test_identity() {
+        assert!(AcceptEncoding::parse("").accepts("identity"));
+        assert!(AcceptEncoding::parse("gzip").accepts("identity"));
+        assert!(!AcceptEncoding::parse("*;q=0").accepts("identity"));
+        assert!(AcceptEncoding::parse("*;q=0, identity").accepts("identity"));
+    }
+
+    #[test]
+    fn test_preferred() {
+        let accept = AcceptEncoding::parse("gzip, br");
+        assert_eq!(accept.preferred(&["br", "gzip"]), Some("br"));
+        assert_eq!(accept.preferred(&["gzip", "br"]), Some("gzip"));
+
+        let accept = AcceptEncoding::parse("br;q=0.2, gzip;q=0.9, deflate;q=invalid");
+        assert_eq!(accept.quality("deflate"), 1.0);
+        assert_eq!(accept.preferred(&["br", "gzip"]), Some("gzip"));
+        assert_eq!(accept.preferred(&["zstd"]), None);
+    }
+}
@@ -0,0 +1,109 @@
+use serde_json::{Map, Value};
+
+use crate::http::{HttpBody, HttpResponse};
+
+/// Configuration of the envelope that wraps successful JSON responses,
+/// e.g. `{ "data": ..., "meta": ... }`.
+///
+/// Only responses with a 2xx status and a `HttpBody::Value` body are wrapped.
+/// Errors keep their own shape and non-JSON bodies are left untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopeConfig {
+    data_key: String,
+    meta_key: String,
+    meta: Option<Value>,
+}
+
+impl Default for EnvelopeConfig {
+    fn default() -> Self {
+        Self {
+            data_key: String::from("data"),
+            meta_key: String::from("meta"),
+            meta: None,
+        }
+    }
+}
+
+impl EnvelopeConfig {
+    /// Create an envelope wrapping the response in a `data` field.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes the config, set the key holding the response value and returns changed config
+    pub fn data_key(mut self, key: &str) -> Self {
+        self.data_key = key.to_string();
+        self
+    }
+
+    /// Consumes the config, set the key holding the metadata and returns changed config
+    pub fn meta_key(mut self, key: &str) -> Self {
+        self.meta_key = key.to_string();
+        self
+    }
+
+    /// Consumes the config, set the metadata added to every envelope and returns changed config
+    pub fn meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Wrap the body of the response in the envelope if it is a successful JSON response.
+    pub fn wrap(&self, res: &mut HttpResponse) {
+        if !(200..300).contains(&res.status_code) {
+            return;
+        }
+        if let HttpBody::Value(ref mut value) = res.body {
+            let mut envelope = Map::new();
+            envelope.insert(self.data_key.clone(), value.take());
+            if let Some(ref meta) = self.meta {
+                envelope.insert(self.meta_key.clone(), meta.clone());
+            }
+            *value = Value::Object(envelope);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::HeaderMap;
+    use serde_json::json;
+
+    fn response(status_code: u16, body: HttpBody) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            headers: HeaderMap::new(),
+            body,
+        }
+    }
+
+    #[test]
+    fn test_wrap_success() {
+        let config = EnvelopeConfig::new().meta(json!({ "version": 1 }));
+        let mut res = response(200, json!({ "id": 1 }).into());
+        config.wrap(&mut res);
+        assert_eq!(
+            res.body,
+            HttpBody::Value(json!({ "data": { "id": 1 }, "meta": { "version": 1 } }))
+        );
+
+        let config = EnvelopeConfig::new().data_key("result");
+        let mut res = response(201, json!([1, 2]).into());
+        config.wrap(&mut res);
+        assert_eq!(res.body, HttpBody::Value(json!({ "result": [1, 2] })));
+    }
+
+    #[test]
+    fn test_skip_errors_and_non_json() {
+        let config = EnvelopeConfig::new();
+        let error = json!({ "statusCode": 400, "message": "Bad Request" });
+        let mut res = response(400, error.clone().into());
+        config.wrap(&mut res);
+        assert_eq!(res.body, HttpBody::Value(error));
+
+        let mut res = response(200, String::from("<h1>Hello</h1>").into());
+        config.wrap(&mut res);
+        assert_eq!(res.body, HttpBody::String(String::from("<h1>Hello</h1>")));
+    }
+}
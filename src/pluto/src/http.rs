@@ -1,17 +1,207 @@
 use crate::{
+    accept::Accept,
+    certification,
+    compression::CompressionConfig,
+    cookie::Cookie,
     cors::Cors,
+    envelope::EnvelopeConfig,
+    json::JsonConfig,
+    jwt::{Jwt, JwtVerifier},
     method::Method,
-    router::{HandlerContainer, Router},
+    middleware::Middleware,
+    multipart::{self, MultipartPart},
+    panic::{panic_message, CatchUnwind},
+    router::{HandlerContainer, Router, TrailingSlash},
+    schema,
+    streaming::{StreamingBody, StreamingStrategy},
 };
-use candid::{CandidType, Deserialize};
-use matchit::{Match, Params as MatchitParams};
+use candid::{CandidType, Deserialize, Principal};
+use flate2::read::GzDecoder;
+use matchit::Params as MatchitParams;
+use percent_encoding::percent_decode_str;
 use serde::Serialize;
 use serde_json::{json, Value};
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    io::Read,
+    panic::{catch_unwind, AssertUnwindSafe},
+    str::FromStr,
+};
+
+/// The limit of `HttpRequest::decoded_body` when `HttpServe::max_body_size` is not set.
+const DEFAULT_MAX_DECODED_BODY_SIZE: usize = 8 * 1024 * 1024;
 
 /// HeaderField is the type of the header of the request.
 #[derive(CandidType, Deserialize, Clone)]
-pub struct HeaderField(String, String);
+pub struct HeaderField(pub(crate) String, pub(crate) String);
+
+/// HeaderMap is the type of the headers of the response.
+/// It keeps the insertion order and allows repeated headers, e.g. several `Set-Cookie`.
+/// Header names are compared case-insensitively.
+/// It is encoded in Candid as the `vec record { text; text }` expected by the IC.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap(Vec<(String, String)>);
+
+impl HeaderMap {
+    /// Create an empty header map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a header, replacing all existing values with the same name.
+    /// The header keeps the position of its first occurrence.
+    /// Returns the first replaced value.
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        match self.position(&key) {
+            Some(index) => {
+                let old = std::mem::replace(&mut self.0[index], (key, value));
+                let mut current = 0;
+                self.0.retain(|(name, _)| {
+                    current += 1;
+                    current - 1 == index || !name.eq_ignore_ascii_case(&old.0)
+                });
+                Some(old.1)
+            }
+            None => {
+                self.0.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Add a header without overwriting existing values with the same name.
+    pub fn append(&mut self, key: String, value: String) {
+        self.0.push((key, value));
+    }
+
+    /// Get the first value of a header.
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.position(key).map(|index| &self.0[index].1)
+    }
+
+    /// Get all values of a header in insertion order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.0
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Remove all values of a header, returning the first removed value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let removed = self.get(key).cloned();
+        self.0.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        removed
+    }
+
+    /// Check if the header is set.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Iterate over the header names, repeated headers are yielded once per value.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(name, _)| name)
+    }
+
+    /// Iterate over the headers in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter().map(|(name, value)| (name, value))
+    }
+
+    /// Get the number of header values.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(key))
+    }
+}
+
+impl FromIterator<(String, String)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<const N: usize> From<[(String, String); N]> for HeaderMap {
+    fn from(headers: [(String, String); N]) -> Self {
+        headers.into_iter().collect()
+    }
+}
+
+impl From<HashMap<String, String>> for HeaderMap {
+    fn from(headers: HashMap<String, String>) -> Self {
+        headers.into_iter().collect()
+    }
+}
+
+impl From<HeaderMap> for Vec<(String, String)> {
+    fn from(headers: HeaderMap) -> Self {
+        headers.0
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Extensions are values attached to a request by middleware for the handler, e.g. verified JWT claims.
+/// They are local to the canister and never part of the Candid encoding of the request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extensions(HashMap<String, Value>);
+
+impl Extensions {
+    /// Attach a value under `key`, returning the replaced value.
+    pub fn insert(&mut self, key: &str, value: Value) -> Option<Value> {
+        self.0.insert(key.to_string(), value)
+    }
+
+    /// Get the value attached under `key`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// Remove the value attached under `key`.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.0.remove(key)
+    }
+
+    /// Check if a value is attached under `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+}
+
+impl CandidType for Extensions {
+    fn _ty() -> candid::types::Type {
+        <()>::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        ().idl_serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Extensions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde::de::IgnoredAny::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
 
 /// RawHttpRequest is the request type that is sent by the client.
 /// It is a raw version of HttpRequest. It is compatible with the Candid type.
@@ -24,17 +214,42 @@ pub struct RawHttpRequest {
     pub(crate) headers: Vec<HeaderField>,
     #[serde(with = "serde_bytes")]
     pub(crate) body: Vec<u8>,
+    /// The highest version of the response verification the gateway supports.
+    /// It is absent for gateways supporting only the certification v1.
+    pub(crate) certificate_version: Option<u16>,
+}
+
+impl RawHttpRequest {
+    /// Get the value of the first header matching `name`, compared case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|HeaderField(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|HeaderField(_, value)| value.as_str())
+    }
 }
 
 impl From<RawHttpRequest> for HttpRequest {
     fn from(req: RawHttpRequest) -> Self {
+        let mut query = HashMap::new();
+        for (key, value) in parse_query(&req.url) {
+            query.entry(key).or_insert(value);
+        }
         HttpRequest {
+            query,
             method: req.method,
             url: req.url,
             headers: req.headers,
             body: req.body.clone(),
+            certificate_version: req.certificate_version,
             params: HashMap::new(),
             path: String::new(),
+            matched_pattern: None,
+            extensions: Extensions::default(),
+            canister_id: None,
+            base_url: None,
+            prefix: String::new(),
+            max_body_size: None,
         }
     }
 }
@@ -49,15 +264,352 @@ pub struct HttpRequest {
     pub headers: Vec<HeaderField>,
     #[serde(with = "serde_bytes")]
     pub body: Vec<u8>,
+    /// The highest version of the response verification the gateway supports, `None` meaning v1.
+    pub certificate_version: Option<u16>,
     pub params: HashMap<String, String>,
+    /// The percent-decoded query parameters, the first value is kept for repeated keys
+    pub query: HashMap<String, String>,
     pub path: String,
+    /// The route pattern the request matched, e.g. `/users/{id}` for the path `/users/42`,
+    /// including the router prefix. `None` until the request is routed.
+    pub matched_pattern: Option<String>,
+    /// Values attached by middleware, e.g. the JWT claims inserted by `HttpServe::use_jwt`
+    pub extensions: Extensions,
+    canister_id: Option<Principal>,
+    base_url: Option<String>,
+    prefix: String,
+    max_body_size: Option<usize>,
 }
 
 impl HttpRequest {
+    /// Get the path relative to the prefix of the router the route was registered under,
+    /// e.g. `/users` for the path `/api/users` registered with the `/api` prefix.
+    pub fn relative_path(&self) -> &str {
+        match self.path.strip_prefix(&self.prefix) {
+            Some("") => "/",
+            Some(relative) => relative,
+            None => &self.path,
+        }
+    }
+
+    /// Get the parsed method of the request, e.g. to branch in a handler registered for several methods.
+    /// It is `None` only for malformed methods, which the router never passes to a handler.
+    ///
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    ///
+    /// async fn handler(req: HttpRequest) -> Result<HttpResponse, HttpResponse> {
+    ///     match req.method_enum() {
+    ///         Some(Method::DELETE) => Ok(HttpResponse::no_content()),
+    ///         _ => Ok(HttpResponse::accepted("/jobs/1")),
+    ///     }
+    /// }
+    /// ```
+    pub fn method_enum(&self) -> Option<Method> {
+        Method::from_str(&self.method).ok()
+    }
+
+    /// Get the principal of the canister serving the request.
+    /// It is useful for building absolute links back to the canister.
+    pub fn canister_id(&self) -> Option<Principal> {
+        self.canister_id
+    }
+
+    /// Build a link to `path` on this canister.
+    /// The link is absolute when `HttpServe::base_url` is configured or the request has a `Host` header,
+    /// otherwise `path` is returned unchanged. Absolute URLs are never modified.
+    pub fn url_for(&self, path: &str) -> String {
+        if path.contains("://") {
+            return path.to_string();
+        }
+        match self.base_url {
+            Some(ref base_url) => join_url(base_url, path),
+            None => match self.header("Host") {
+                Some(host) => join_url(&format!("https://{}", host), path),
+                None => path.to_string(),
+            },
+        }
+    }
+
+    /// Get the value of the first header matching `name`, compared case-insensitively,
+    /// e.g. `req.header("authorization")` reads the `Authorization` header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|HeaderField(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|HeaderField(_, value)| value.as_str())
+    }
+
+    /// Get the values of all headers matching `name`, compared case-insensitively, in request order.
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|HeaderField(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|HeaderField(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Get the token of an `Authorization: Bearer <token>` header.
+    /// The scheme is compared case-insensitively.
+    pub fn bearer_token(&self) -> Option<&str> {
+        let (scheme, token) = self.header("Authorization")?.trim().split_once(' ')?;
+        let token = token.trim();
+        if !scheme.eq_ignore_ascii_case("Bearer") || token.is_empty() {
+            return None;
+        }
+        Some(token)
+    }
+
+    /// Parse the cookies of the `Cookie` headers, with percent-decoded values.
+    /// Pairs without `=` are skipped, the first value is kept for repeated names.
+    /// Returns an empty map if the request has no cookies.
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        for header in self.headers_all("Cookie") {
+            for pair in header.split(';') {
+                let (name, value) = match pair.split_once('=') {
+                    Some((name, value)) => (name.trim(), value.trim()),
+                    None => continue,
+                };
+                if name.is_empty() {
+                    continue;
+                }
+                let value = value.trim_matches('"');
+                let value = percent_decode_str(value).decode_utf8_lossy().into_owned();
+                cookies.entry(name.to_string()).or_insert(value);
+            }
+        }
+        cookies
+    }
+
+    /// Get the percent-decoded value of the cookie `name`.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    /// Get the JWT claims verified by `HttpServe::use_jwt`.
+    pub fn claims(&self) -> Option<&Value> {
+        self.extensions.get(crate::jwt::CLAIMS)
+    }
+
+    /// Get the decoded value of the first query parameter matching `key`.
+    pub(crate) fn query_param(&self, key: &str) -> Option<String> {
+        parse_query(&self.url)
+            .into_iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Parse every value of a repeated query parameter, e.g. `?id=1&id=2&id=3`.
+    /// Returns an empty vector if the parameter is absent and a 400 response if any value fails to parse.
+    pub fn query_vec<T: FromStr>(&self, key: &str) -> Result<Vec<T>, HttpResponse> {
+        parse_query(&self.url)
+            .into_iter()
+            .filter(|(name, _)| name == key)
+            .map(|(_, value)| {
+                value.parse::<T>().map_err(|_| HttpResponse {
+                    status_code: 400,
+                    headers: HeaderMap::new(),
+                    body: json!({
+                        "statusCode": 400,
+                        "message": format!("invalid value '{}' for query parameter '{}'", value, key),
+                    })
+                    .into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Parse the path parameter `name`, e.g. `id` of `/users/{id}`.
+    /// Returns a 400 response if the value fails to parse and a 500 response if the route has no such parameter.
+    ///
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    ///
+    /// async fn get_user(req: HttpRequest) -> Result<HttpResponse, HttpResponse> {
+    ///     let id: u64 = req.param("id")?;
+    ///     Ok(HttpResponse::json(200, &id))
+    /// }
+    /// ```
+    pub fn param<T: FromStr>(&self, name: &str) -> Result<T, HttpResponse> {
+        let value = match self.params.get(name) {
+            Some(value) => value,
+            None => {
+                return Err(HttpResponse {
+                    status_code: 500,
+                    headers: HeaderMap::new(),
+                    body: json!({
+                        "statusCode": 500,
+                        "message": format!("unknown path parameter '{}'", name),
+                        "error": "Internal Server Error"
+                    })
+                    .into(),
+                })
+            }
+        };
+        value.parse::<T>().map_err(|_| HttpResponse {
+            status_code: 400,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 400,
+                "message": format!("invalid value '{}' for '{}'", value, name),
+            })
+            .into(),
+        })
+    }
+
+    /// Ensure the request body has the `expected` content type, e.g. `application/json`.
+    /// Parameters such as `charset` are ignored during the comparison.
+    /// Returns a 415 Unsupported Media Type response otherwise.
+    pub fn require_content_type(&self, expected: &str) -> Result<(), HttpResponse> {
+        if self.media_type().eq_ignore_ascii_case(expected.trim()) {
+            return Ok(());
+        }
+        Err(Self::unsupported_media_type(expected))
+    }
+
+    /// Get the raw protobuf body after checking the request content type is
+    /// `application/x-protobuf`, `application/protobuf` or `application/grpc-web+proto`.
+    /// Returns a 415 Unsupported Media Type response otherwise.
+    pub fn protobuf_body(&self) -> Result<&[u8], HttpResponse> {
+        let media_type = self.media_type();
+        let accepted = [
+            "application/x-protobuf",
+            "application/protobuf",
+            "application/grpc-web+proto",
+        ]
+        .iter()
+        .any(|expected| media_type.eq_ignore_ascii_case(expected));
+        if accepted {
+            return Ok(&self.body);
+        }
+        Err(Self::unsupported_media_type("application/x-protobuf"))
+    }
+
+    /// Parse a `multipart/form-data` body, e.g. a form with file uploads, into its parts.
+    /// Every part exposes its field `name`, the optional `filename`, its `content_type` and the raw `data`.
+    /// Returns a 400 Bad Request response if the boundary is missing or the body is malformed.
+    pub fn multipart(&self) -> Result<Vec<MultipartPart>, HttpResponse> {
+        match self.header("Content-Type").and_then(multipart::boundary) {
+            Some(boundary) => multipart::parse(&self.body, &boundary),
+            None => Err(multipart::malformed("Multipart boundary is missing")),
+        }
+    }
+
+    /// Check if the client accepts `mime` according to the `Accept` header.
+    /// A request without an `Accept` header accepts everything.
+    pub fn accepts(&self, mime: &str) -> bool {
+        self.accept().accepts(mime)
+    }
+
+    /// Pick the media type from `offered` the client prefers according to the `Accept` header,
+    /// e.g. to answer with JSON or HTML from a single handler.
+    /// Ties are resolved by the order of `offered`, `None` means none of them is acceptable.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    ///
+    /// async fn user(req: HttpRequest) -> Result<HttpResponse, HttpResponse> {
+    ///     match req.preferred_content_type(&["application/json", "text/html"]) {
+    ///         Some("text/html") => Ok(HttpResponse::builder().text("<h1>Pluto</h1>".to_string()).build()),
+    ///         _ => Ok(HttpResponse::json(200, &serde_json::json!({ "name": "Pluto" }))),
+    ///     }
+    /// }
+    /// ```
+    pub fn preferred_content_type<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        self.accept().preferred(offered)
+    }
+
+    fn accept(&self) -> Accept {
+        self.header("Accept").map(Accept::parse).unwrap_or_default()
+    }
+
+    /// Get the media type of the `Content-Type` header without its parameters.
+    fn media_type(&self) -> &str {
+        self.header("Content-Type")
+            .and_then(|value| value.split(';').next())
+            .unwrap_or("")
+            .trim()
+    }
+
+    fn unsupported_media_type(expected: &str) -> HttpResponse {
+        HttpResponse {
+            status_code: 415,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 415,
+                "message": format!("Expected content type '{}'", expected),
+                "error": "Unsupported Media Type"
+            })
+            .into(),
+        }
+    }
+
+    /// Enforce the `If-Match` precondition against the current entity tag of the resource,
+    /// `None` meaning the resource does not exist.
+    /// Requests without `If-Match` always pass, `*` matches any existing resource
+    /// and weak tags never match. Returns a 412 Precondition Failed response otherwise.
+    ///
+    /// Together with `HttpResponse::no_content` it gives idempotent deletes:
+    ///
+    /// ```ignore
+    /// router.delete("/items/{id}", false, |req: HttpRequest| async move {
+    ///     let etag = current_etag(&req.params["id"]);
+    ///     req.require_if_match(etag.as_deref())?;
+    ///     delete_item(&req.params["id"]);
+    ///     // The same response whether or not the item existed
+    ///     Ok(HttpResponse::no_content())
+    /// });
+    /// ```
+    pub fn require_if_match(&self, etag: Option<&str>) -> Result<(), HttpResponse> {
+        let header = match self.header("If-Match") {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+        let matches = etag.is_some_and(|etag| {
+            let etag = etag.trim().trim_matches('"');
+            header.split(',').map(str::trim).any(|candidate| {
+                candidate == "*"
+                    || (!candidate.starts_with("W/") && candidate.trim_matches('"') == etag)
+            })
+        });
+        if matches {
+            return Ok(());
+        }
+        Err(HttpResponse {
+            status_code: 412,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 412,
+                "message": "The resource does not match the If-Match precondition",
+                "error": "Precondition Failed"
+            })
+            .into(),
+        })
+    }
+
+    /// Validate the JSON body against a JSON schema, see `schema::validate` for the supported keywords.
+    /// Returns a 400 response if the body is not JSON and a 422 Unprocessable Entity response
+    /// listing every violation otherwise.
+    pub fn validate_schema(&self, schema: &Value) -> Result<(), HttpResponse> {
+        let body: Value = self.body_into_struct()?;
+        let errors = schema::validate(schema, &body);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(schema::validation_error(errors))
+    }
+
     pub fn body_into_struct<T: for<'a> Deserialize<'a>>(&self) -> Result<T, HttpResponse> {
         serde_json::from_slice(&self.body).map_err(|msg| HttpResponse {
             status_code: 400,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 400,
                 "message": msg.to_string(),
@@ -66,11 +618,128 @@ impl HttpRequest {
         })
     }
 
+    /// Deserialize the body according to the `Content-Type` header: MessagePack for
+    /// `application/msgpack`, `application/x-msgpack` and `application/vnd.msgpack`
+    /// (requires the `msgpack` feature), JSON otherwise.
+    /// Returns a 400 response if the body can not be decoded, and a 415 response
+    /// for MessagePack bodies when the `msgpack` feature is disabled.
+    pub fn decode_body<T: for<'a> Deserialize<'a>>(&self) -> Result<T, HttpResponse> {
+        let msgpack = [
+            "application/msgpack",
+            "application/x-msgpack",
+            "application/vnd.msgpack",
+        ]
+        .iter()
+        .any(|mime| self.media_type().eq_ignore_ascii_case(mime));
+        if !msgpack {
+            return self.body_into_struct();
+        }
+        #[cfg(feature = "msgpack")]
+        {
+            rmp_serde::from_slice(&self.body).map_err(|msg| HttpResponse {
+                status_code: 400,
+                headers: HeaderMap::new(),
+                body: json!({
+                    "statusCode": 400,
+                    "message": msg.to_string(),
+                })
+                .into(),
+            })
+        }
+        #[cfg(not(feature = "msgpack"))]
+        Err(Self::unsupported_media_type("application/json"))
+    }
+
+    /// Get the body inflated according to the `Content-Encoding` header,
+    /// so clients may send gzip compressed bodies. Bodies without a content coding are returned as they are.
+    /// The inflated size is limited by `HttpServe::max_body_size`, or 8 MiB when it is not set,
+    /// which guards against decompression bombs.
+    /// Returns a 400 response for a corrupt gzip stream, a 413 response for a body inflating
+    /// over the limit and a 415 response for other content codings.
+    pub fn decoded_body(&self) -> Result<Vec<u8>, HttpResponse> {
+        let encoding = self.header("Content-Encoding").unwrap_or("identity").trim();
+        if encoding.eq_ignore_ascii_case("identity") {
+            return Ok(self.body.clone());
+        }
+        if !encoding.eq_ignore_ascii_case("gzip") && !encoding.eq_ignore_ascii_case("x-gzip") {
+            return Err(HttpResponse {
+                status_code: 415,
+                headers: HeaderMap::from([("Accept-Encoding".to_string(), "gzip".to_string())]),
+                body: json!({
+                    "statusCode": 415,
+                    "message": format!("Unsupported content encoding '{}'", encoding),
+                    "error": "Unsupported Media Type"
+                })
+                .into(),
+            });
+        }
+
+        let limit = self.max_body_size.unwrap_or(DEFAULT_MAX_DECODED_BODY_SIZE);
+        let mut decoded = Vec::new();
+        let read = GzDecoder::new(self.body.as_slice())
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decoded);
+        match read {
+            Err(err) => Err(HttpResponse {
+                status_code: 400,
+                headers: HeaderMap::new(),
+                body: json!({
+                    "statusCode": 400,
+                    "message": format!("Invalid gzip body: {}", err),
+                })
+                .into(),
+            }),
+            Ok(_) if decoded.len() > limit => Err(HttpResponse {
+                status_code: 413,
+                headers: HeaderMap::new(),
+                body: json!({
+                    "statusCode": 413,
+                    "message": format!("Decoded request body exceeds the limit of {} bytes", limit),
+                    "error": "Payload Too Large"
+                })
+                .into(),
+            }),
+            Ok(_) => Ok(decoded),
+        }
+    }
+
     pub fn params_into_struct<T: for<'a> Deserialize<'a>>(&self) -> Result<T, HttpResponse> {
         let json = serde_json::json!(&self.params);
         serde_json::from_value(json).map_err(|msg| HttpResponse {
             status_code: 400,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 400,
+                "message": msg.to_string(),
+            })
+            .into(),
+        })
+    }
+
+    /// Deserialize a struct from the query parameters, e.g. `?page=2&limit=10`.
+    /// Like in `params_into_struct`, all values are passed as strings.
+    pub fn query_into_struct<T: for<'a> Deserialize<'a>>(&self) -> Result<T, HttpResponse> {
+        let json = serde_json::json!(&self.query);
+        serde_json::from_value(json).map_err(|msg| HttpResponse {
+            status_code: 400,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 400,
+                "message": msg.to_string(),
+            })
+            .into(),
+        })
+    }
+
+    /// Deserialize a struct from both the path params and the query parameters.
+    /// Path params take precedence over query parameters with the same name.
+    /// Like in `params_into_struct`, all values are passed as strings.
+    pub fn extract<T: for<'a> Deserialize<'a>>(&self) -> Result<T, HttpResponse> {
+        let mut fields: HashMap<String, String> = parse_query(&self.url).into_iter().collect();
+        fields.extend(self.params.clone());
+        serde_json::from_value(json!(fields)).map_err(|msg| HttpResponse {
+            status_code: 400,
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 400,
                 "message": msg.to_string(),
@@ -78,6 +747,112 @@ impl HttpRequest {
             .into(),
         })
     }
+
+    /// Get the path params as a JSON object of strings, e.g. `{ "id": "42" }`.
+    pub fn params_value(&self) -> Value {
+        json!(self.params)
+    }
+
+    /// Get the query parameters as a JSON object.
+    /// Values are strings, parameters repeated in the query are collected into an array
+    /// in their order, e.g. `?tag=a&tag=b&page=2` gives `{ "tag": ["a", "b"], "page": "2" }`.
+    pub fn query_value(&self) -> Value {
+        let mut object = serde_json::Map::new();
+        for (key, value) in parse_query(&self.url) {
+            match object.get_mut(&key) {
+                Some(Value::Array(values)) => values.push(Value::String(value)),
+                Some(existing) => {
+                    let first = existing.take();
+                    *existing = Value::Array(vec![first, Value::String(value)]);
+                }
+                None => {
+                    object.insert(key, Value::String(value));
+                }
+            }
+        }
+        Value::Object(object)
+    }
+}
+
+/// Join a base URL and a path, avoiding a doubled or missing slash.
+fn join_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Percent-decode the request path for matching, e.g. `/users/%20foo` to `/users/ foo`.
+/// `%2F` and `%25` stay encoded, so an encoded slash does not start a new segment,
+/// and a `%` not starting an escape is encoded as `%25`, so the result can be decoded once more.
+/// Returns `None` if the decoded path is not valid UTF-8.
+pub(crate) fn decode_path(path: &str) -> Option<String> {
+    let hex = |byte: Option<&u8>| byte.and_then(|byte| (*byte as char).to_digit(16));
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] != b'%' {
+            decoded.push(bytes[index]);
+            index += 1;
+            continue;
+        }
+        match (hex(bytes.get(index + 1)), hex(bytes.get(index + 2))) {
+            (Some(high), Some(low)) => {
+                let byte = (high * 16 + low) as u8;
+                if byte == b'/' || byte == b'%' {
+                    decoded.extend_from_slice(&bytes[index..index + 3]);
+                } else {
+                    decoded.push(byte);
+                }
+                index += 3;
+            }
+            _ => {
+                decoded.extend_from_slice(b"%25");
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Split the query string of the url into decoded key-value pairs, preserving their order.
+pub(crate) fn parse_query(url: &str) -> Vec<(String, String)> {
+    let query = match url.split_once('?') {
+        Some((_, query)) => query.split('#').next().unwrap_or(""),
+        None => return Vec::new(),
+    };
+    let decode = |s: &str| {
+        percent_decode_str(&s.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+impl RawHttpRequest {
+    /// Build a request for unit tests.
+    pub(crate) fn test(method: &str, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Self {
+        RawHttpRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| HeaderField(key.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_vec(),
+            certificate_version: None,
+        }
+    }
 }
 
 /// RawHttpResponse is the response type that is sent back to the client.
@@ -85,13 +860,48 @@ impl HttpRequest {
 #[derive(CandidType, Deserialize)]
 pub struct RawHttpResponse {
     pub(crate) status_code: u16,
-    pub(crate) headers: HashMap<String, String>,
+    pub(crate) headers: HeaderMap,
     #[serde(with = "serde_bytes")]
     pub(crate) body: Vec<u8>,
     pub(crate) upgrade: Option<bool>,
+    pub(crate) streaming_strategy: Option<StreamingStrategy>,
+}
+
+/// The final response of a route, passed to its finalizer registered with `Router::finalize`.
+/// The body is the encoded bytes sent to the client, after all response plugins.
+pub struct ResponseContext<'a> {
+    pub status_code: u16,
+    pub body: &'a [u8],
+    headers: &'a mut HeaderMap,
+}
+
+impl ResponseContext<'_> {
+    /// Get the first value of a response header.
+    pub fn header(&self, key: &str) -> Option<&String> {
+        self.headers.get(key)
+    }
+
+    /// Set a response header, replacing all existing values with the same name.
+    pub fn set_header(&mut self, key: &str, value: String) {
+        self.headers.insert(key.to_string(), value);
+    }
+
+    /// Add a response header without overwriting existing values with the same name.
+    pub fn append_header(&mut self, key: &str, value: String) {
+        self.headers.append(key.to_string(), value);
+    }
 }
 
 impl RawHttpResponse {
+    /// Build the finalizer context over the response.
+    pub(crate) fn context(&mut self) -> ResponseContext<'_> {
+        ResponseContext {
+            status_code: self.status_code,
+            body: &self.body,
+            headers: &mut self.headers,
+        }
+    }
+
     /// Set the upgrade flag of the response.
     fn set_upgrade(&mut self, upgrade: bool) {
         self.upgrade = Some(upgrade);
@@ -115,6 +925,8 @@ pub enum HttpBody {
     Value(Value),
     String(String),
     Raw(Vec<u8>),
+    /// The first chunk of a body streamed in several messages, see `HttpResponse::stream`
+    Stream(Box<StreamingBody>),
 }
 
 impl From<HttpBody> for Vec<u8> {
@@ -123,6 +935,7 @@ impl From<HttpBody> for Vec<u8> {
             HttpBody::Value(json) => json.to_string().into_bytes().into(),
             HttpBody::String(string) => string.into_bytes().into(),
             HttpBody::Raw(vec) => vec,
+            HttpBody::Stream(stream) => stream.chunk,
         };
     }
 }
@@ -151,31 +964,498 @@ impl From<Vec<u8>> for HttpBody {
 #[derive(Debug, PartialEq, Clone)]
 pub struct HttpResponse {
     pub status_code: u16,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
     pub body: HttpBody,
 }
 
 impl HttpResponse {
-    /// Add a header to the response.
-    /// If the header already exists, it will be overwritten.
-    pub fn add_raw_header(&mut self, key: &str, value: String) {
-        self.headers.insert(key.to_string(), value);
+    /// Start building a response, with the status code 200 and an empty body by default.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::HttpResponse;
+    /// use serde_json::json;
+    ///
+    /// let res = HttpResponse::builder()
+    ///     .status(201)
+    ///     .header("X-Foo", "bar")
+    ///     .json(json!({ "id": 1 }))
+    ///     .build();
+    /// assert_eq!(res.status_code, 201);
+    /// ```
+    pub fn builder() -> HttpResponseBuilder {
+        HttpResponseBuilder::default()
     }
 
-    /// Remove a header from the response.
-    /// If the header does not exist, nothing will happen.
-    pub fn remove_header(&mut self, key: &str) {
-        self.headers.remove(key);
+    /// Build a JSON response from any serializable value, e.g. a struct of the handler.
+    /// Returns a 500 response if the value can not be serialized.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::HttpResponse;
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct User {
+    ///     id: u64,
+    /// }
+    ///
+    /// let res = HttpResponse::json(201, &User { id: 1 });
+    /// assert_eq!(res.status_code, 201);
+    /// ```
+    pub fn json<T: Serialize + ?Sized>(status_code: u16, value: &T) -> HttpResponse {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self::json_bytes(status_code, body),
+            Err(_) => HttpServe::internal_server_error().unwrap_err(),
+        }
     }
-}
 
-impl From<HttpResponse> for RawHttpResponse {
-    fn from(res: HttpResponse) -> Self {
-        let mut res = RawHttpResponse {
+    /// Build a JSON response from already serialized bytes, sent as is without a parse and re-serialize round trip.
+    /// The bytes are not validated.
+    pub fn json_bytes(status_code: u16, body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            headers: HeaderMap::from([(
+                "Content-Type".to_string(),
+                "application/json".to_string(),
+            )]),
+            body: HttpBody::Raw(body),
+        }
+    }
+
+    /// Build a 200 JSON response serialized according to `config`,
+    /// e.g. with sorted keys for a deterministic output.
+    /// The body is stored as raw bytes, so the serialized form is sent as is.
+    /// Returns a 500 response if the value can not be serialized.
+    pub fn json_with<T: Serialize + ?Sized>(
+        value: &T,
+        config: &JsonConfig,
+    ) -> Result<HttpResponse, HttpResponse> {
+        match config.to_vec(value) {
+            Ok(body) => Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::from([(
+                    "Content-Type".to_string(),
+                    "application/json".to_string(),
+                )]),
+                body: HttpBody::Raw(body),
+            }),
+            Err(_) => Err(HttpServe::internal_server_error().unwrap_err()),
+        }
+    }
+
+    /// Build a 200 response with a binary protobuf body.
+    pub fn protobuf(body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([(
+                "Content-Type".to_string(),
+                "application/x-protobuf".to_string(),
+            )]),
+            body: HttpBody::Raw(body),
+        }
+    }
+
+    /// Build a 200 CSV download named `filename`, written row by row from `rows` after the `headers` row.
+    /// Fields are quoted per RFC 4180 when they contain a comma, a quote or a line break,
+    /// and rows end with CRLF.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::HttpResponse;
+    ///
+    /// let users = vec![("1", "Doe, John"), ("2", "Jane")];
+    /// let res = HttpResponse::csv(
+    ///     "users.csv",
+    ///     &["id", "name"],
+    ///     users.iter().map(|(id, name)| [*id, *name]),
+    /// );
+    /// ```
+    pub fn csv<R, F>(filename: &str, headers: &[&str], rows: R) -> HttpResponse
+    where
+        R: IntoIterator,
+        R::Item: IntoIterator<Item = F>,
+        F: AsRef<str>,
+    {
+        let mut body = Vec::new();
+        Self::write_csv_row(&mut body, headers);
+        for row in rows {
+            Self::write_csv_row(&mut body, row);
+        }
+        HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([
+                (
+                    "Content-Type".to_string(),
+                    "text/csv; charset=utf-8".to_string(),
+                ),
+                (
+                    "Content-Disposition".to_string(),
+                    format!(
+                        "attachment; filename=\"{}\"",
+                        filename.replace('\\', "\\\\").replace('"', "\\\"")
+                    ),
+                ),
+            ]),
+            body: HttpBody::Raw(body),
+        }
+    }
+
+    fn write_csv_row<F: AsRef<str>>(body: &mut Vec<u8>, row: impl IntoIterator<Item = F>) {
+        for (index, field) in row.into_iter().enumerate() {
+            if index > 0 {
+                body.push(b',');
+            }
+            let field = field.as_ref();
+            if field.contains([',', '"', '\r', '\n']) {
+                body.push(b'"');
+                body.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+                body.push(b'"');
+            } else {
+                body.extend_from_slice(field.as_bytes());
+            }
+        }
+        body.extend_from_slice(b"\r\n");
+    }
+
+    /// Build a 200 response streaming `content` in chunks of `streaming::CHUNK_SIZE` bytes,
+    /// e.g. for files too big for a single IC message.
+    /// The first chunk is sent right away, the gateway fetches the others by calling the `callback`
+    /// query method of the canister with a token holding `key`, see `streaming::http_request_streaming_callback`.
+    /// Streamed bodies are never compressed or tagged by the response plugins.
+    /// # Examples
+    ///
+    /// ```ignore
+    /// router.get("/video.mp4", false, |_req: HttpRequest| async move {
+    ///     let mut res = HttpResponse::stream(VIDEO, "video.mp4", "http_request_streaming_callback");
+    ///     res.add_raw_header("Content-Type", "video/mp4".to_string());
+    ///     Ok(res)
+    /// });
+    /// ```
+    pub fn stream(content: &[u8], key: &str, callback: &str) -> HttpResponse {
+        HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: HttpBody::Stream(Box::new(StreamingBody::new(content, key, callback))),
+        }
+    }
+
+    /// Build a 200 response with a stylesheet body.
+    pub fn css(body: String) -> HttpResponse {
+        Self::with_content_type(body, "text/css; charset=utf-8")
+    }
+
+    /// Build a 200 response with a JavaScript body.
+    pub fn javascript(body: String) -> HttpResponse {
+        Self::with_content_type(body, "text/javascript; charset=utf-8")
+    }
+
+    /// Build a 200 response with an SVG image body.
+    pub fn svg(body: String) -> HttpResponse {
+        Self::with_content_type(body, "image/svg+xml")
+    }
+
+    fn with_content_type(body: String, content_type: &str) -> HttpResponse {
+        HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([("Content-Type".to_string(), content_type.to_string())]),
+            body: body.into(),
+        }
+    }
+
+    /// Predefined 302 Found response redirecting the client to `location`.
+    pub fn redirect(location: &str) -> HttpResponse {
+        Self::redirect_with_status(302, location)
+    }
+
+    /// Predefined 301 Moved Permanently response redirecting the client to `location`.
+    pub fn permanent_redirect(location: &str) -> HttpResponse {
+        Self::redirect_with_status(301, location)
+    }
+
+    /// Build a redirect response with the given 3xx status code, e.g. 303, 307 or 308.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status_code` is not in the 3xx range.
+    pub fn redirect_with_status(status_code: u16, location: &str) -> HttpResponse {
+        if !(300..400).contains(&status_code) {
+            panic!("expect a 3xx redirect status code, found: {}", status_code);
+        }
+        HttpResponse {
+            status_code,
+            headers: HeaderMap::from([("Location".to_string(), location.to_string())]),
+            body: String::new().into(),
+        }
+    }
+
+    /// Predefined 202 Accepted response for work deferred e.g. with the `jobs` queue.
+    /// `location` points the client to the URL reporting the status of the work.
+    pub fn accepted(location: &str) -> HttpResponse {
+        HttpResponse {
+            status_code: 202,
+            headers: HeaderMap::from([("Location".to_string(), location.to_string())]),
+            body: json!({
+                "statusCode": 202,
+                "message": "Accepted",
+                "location": location
+            })
+            .into(),
+        }
+    }
+
+    /// Predefined empty 204 No Content response, e.g. for a successful DELETE.
+    pub fn no_content() -> HttpResponse {
+        HttpResponse {
+            status_code: 204,
+            headers: HeaderMap::new(),
+            body: String::new().into(),
+        }
+    }
+
+    /// Predefined service unavailable response, e.g. for backpressure or maintenance.
+    /// When `retry_after_secs` is set, the `Retry-After` header tells the client
+    /// how many seconds to wait before retrying.
+    pub fn service_unavailable(retry_after_secs: Option<u64>) -> HttpResponse {
+        let mut res = HttpResponse {
+            status_code: 503,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 503,
+                "message": "Service Unavailable",
+                "error": "Service Unavailable"
+            })
+            .into(),
+        };
+        if let Some(seconds) = retry_after_secs {
+            res.add_raw_header("Retry-After", seconds.to_string());
+        }
+        res
+    }
+
+    /// Add a header to the response.
+    /// If the header already exists, all its values will be overwritten.
+    pub fn add_raw_header(&mut self, key: &str, value: String) {
+        self.headers.insert(key.to_string(), value);
+    }
+
+    /// Add a header to the response, keeping existing values with the same name,
+    /// e.g. for headers that may be repeated like `Set-Cookie`.
+    pub fn append_header(&mut self, key: &str, value: String) {
+        self.headers.append(key.to_string(), value);
+    }
+
+    /// Add a `Set-Cookie` header for `cookie`.
+    /// Every cookie gets its own header, so setting several cookies never overwrites the previous ones.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        self.append_header("Set-Cookie", cookie.to_string());
+    }
+
+    /// Remove a header from the response, with all its values.
+    /// If the header does not exist, nothing will happen.
+    pub fn remove_header(&mut self, key: &str) {
+        self.headers.remove(key);
+    }
+
+    /// Add a RFC 8288 web link to the `Link` header, e.g. for pagination with `rel="next"`.
+    /// Links added before are kept, the new one is appended to the list.
+    pub fn add_link(&mut self, url: &str, rel: &str) {
+        let link = format!("<{}>; rel=\"{}\"", url, rel);
+        let value = match self.headers.get("Link") {
+            Some(existing) => format!("{}, {}", existing, link),
+            None => link,
+        };
+        self.add_raw_header("Link", value);
+    }
+
+    /// Copy the headers of `other` that are not already set on this response.
+    /// Header names are compared case-insensitively and existing values always win.
+    pub fn merge_headers_from(&mut self, other: &HttpResponse) {
+        // Compare against the original headers, so repeated headers of `other` are all copied
+        let missing: Vec<(String, String)> = other
+            .headers
+            .iter()
+            .filter(|(key, _)| !self.headers.contains_key(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        for (key, value) in missing {
+            self.headers.append(key, value);
+        }
+    }
+
+    /// Combine this response, usually produced by a handler, with a partial `base` response,
+    /// usually produced by a middleware.
+    ///
+    /// The status code of this response is kept, its body is kept unless it is empty,
+    /// and headers are the union of both with this response taking priority on conflicts.
+    pub fn merge(mut self, base: HttpResponse) -> HttpResponse {
+        self.merge_headers_from(&base);
+        let is_empty = match self.body {
+            HttpBody::Value(ref value) => value.is_null(),
+            HttpBody::String(ref string) => string.is_empty(),
+            HttpBody::Raw(ref raw) => raw.is_empty(),
+            HttpBody::Stream(_) => false,
+        };
+        if is_empty {
+            self.body = base.body;
+        }
+        self
+    }
+
+    /// Set an `ETag` header computed from the body and answer `304 Not Modified` with an empty body
+    /// when the `If-None-Match` header of `req` lists the same tag (or `*`).
+    /// The tag is a FNV-1a hash of the body bytes, so it stays the same across canister upgrades
+    /// as long as the body does not change. Only 200 responses are tagged.
+    /// The tag describes the body before compression, so `HttpServe::compression` weakens it to `W/"…"`
+    /// on the responses it gzips, and revalidating with the weak tag still gets a 304.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    ///
+    /// async fn stylesheet(req: HttpRequest) -> Result<HttpResponse, HttpResponse> {
+    ///     Ok(HttpResponse::css("body { color: red }".to_string()).with_etag(&req))
+    /// }
+    /// ```
+    pub fn with_etag(mut self, req: &HttpRequest) -> HttpResponse {
+        if self.status_code != 200 || matches!(self.body, HttpBody::Stream(_)) {
+            return self;
+        }
+        let body: Vec<u8> = self.body.clone().into();
+        let etag = format!("\"{:x}-{:016x}\"", body.len(), fnv1a(&body));
+        self.add_raw_header("ETag", etag.clone());
+
+        let not_modified = req.header("If-None-Match").is_some_and(|header| {
+            header.split(',').map(str::trim).any(|candidate| {
+                candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+            })
+        });
+        if !not_modified {
+            return self;
+        }
+        // A 304 carries only the headers describing the cached representation
+        let mut headers = HeaderMap::new();
+        for (key, value) in self.headers.iter() {
+            let kept = [
+                "ETag",
+                "Cache-Control",
+                "Vary",
+                "Expires",
+                "Content-Location",
+            ]
+            .iter()
+            .any(|name| key.eq_ignore_ascii_case(name));
+            if kept {
+                headers.append(key.clone(), value.clone());
+            }
+        }
+        HttpResponse {
+            status_code: 304,
+            headers,
+            body: HttpBody::Raw(Vec::new()),
+        }
+    }
+}
+
+/// Hash `data` with the 64-bit FNV-1a function, which does not depend on the compiler or a random seed.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A fluent builder of `HttpResponse`, created with `HttpResponse::builder`.
+/// The body setters also set the matching `Content-Type`,
+/// a `Content-Type` set with `header` afterwards takes precedence.
+#[derive(Debug, Clone)]
+pub struct HttpResponseBuilder {
+    status_code: u16,
+    headers: HeaderMap,
+    body: HttpBody,
+    // Set when the `json` body failed to serialize
+    failed: bool,
+}
+
+impl Default for HttpResponseBuilder {
+    fn default() -> Self {
+        Self {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: String::new().into(),
+            failed: false,
+        }
+    }
+}
+
+impl HttpResponseBuilder {
+    /// Consumes the builder, set the status code and returns changed builder
+    pub fn status(mut self, status_code: u16) -> Self {
+        self.status_code = status_code;
+        self
+    }
+
+    /// Consumes the builder, set a header, replacing the values with the same name, and returns changed builder
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Consumes the builder, set a JSON body and returns changed builder.
+    /// If the value can not be serialized, `build` returns a 500 response.
+    pub fn json(mut self, value: impl Serialize) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.failed = false;
+                self.body = HttpBody::Value(value);
+            }
+            Err(_) => self.failed = true,
+        }
+        self.content_type("application/json")
+    }
+
+    /// Consumes the builder, set a plain text body and returns changed builder
+    pub fn text(mut self, body: String) -> Self {
+        self.body = HttpBody::String(body);
+        self.failed = false;
+        self.content_type("text/plain; charset=utf-8")
+    }
+
+    /// Consumes the builder, set a binary body and returns changed builder
+    pub fn raw(mut self, body: Vec<u8>) -> Self {
+        self.body = HttpBody::Raw(body);
+        self.failed = false;
+        self.content_type("application/octet-stream")
+    }
+
+    fn content_type(self, mime: &str) -> Self {
+        self.header("Content-Type", mime)
+    }
+
+    /// Build the response.
+    pub fn build(self) -> HttpResponse {
+        if self.failed {
+            return HttpServe::internal_server_error().unwrap_err();
+        }
+        HttpResponse {
+            status_code: self.status_code,
+            headers: self.headers,
+            body: self.body,
+        }
+    }
+}
+
+impl From<HttpResponse> for RawHttpResponse {
+    fn from(res: HttpResponse) -> Self {
+        let streaming_strategy = match res.body {
+            HttpBody::Stream(ref stream) => stream.strategy(),
+            _ => None,
+        };
+        let mut res = RawHttpResponse {
             status_code: res.status_code,
             headers: res.headers,
             body: res.body.into(),
             upgrade: Some(false),
+            streaming_strategy,
         };
         res.enrich_header();
         res
@@ -284,6 +1564,12 @@ macro_rules! http_serve {
     }};
 }
 
+/// A hook decorating error responses, registered with `HttpServe::use_error_middleware`.
+type ErrorMiddleware = Box<dyn Fn(&mut HttpResponse) + Send + Sync>;
+
+/// A hook building the response of a panicked handler, registered with `HttpServe::on_panic`.
+type PanicHandler = Box<dyn Fn(&str) -> HttpResponse + Send + Sync>;
+
 /// HttpServe is the main struct of the Pluto library.
 /// It is used to create a new instance of HttpServe.
 /// It is used in the 'http_request' and 'http_request_update' function of the canister.
@@ -293,6 +1579,21 @@ pub struct HttpServe {
     router: Router,
     cors_policy: Option<Cors>,
     is_query: bool,
+    field_selection: bool,
+    pointer_projection: bool,
+    skip_null_fields: bool,
+    pretty_print: bool,
+    powered_by_version: bool,
+    envelope: Option<EnvelopeConfig>,
+    compression: Option<CompressionConfig>,
+    max_response_size: Option<usize>,
+    max_body_size: Option<usize>,
+    instruction_budget: Option<u64>,
+    base_url: Option<String>,
+    error_middlewares: Vec<ErrorMiddleware>,
+    panic_handler: Option<PanicHandler>,
+    middlewares: Vec<Box<dyn Middleware>>,
+    canister_id: Option<Principal>,
 }
 
 impl HttpServe {
@@ -306,6 +1607,21 @@ impl HttpServe {
             router: Router::new(),
             cors_policy: None,
             is_query: created_in_query,
+            field_selection: false,
+            pointer_projection: false,
+            skip_null_fields: false,
+            pretty_print: false,
+            powered_by_version: false,
+            envelope: None,
+            compression: None,
+            max_response_size: None,
+            max_body_size: None,
+            instruction_budget: None,
+            base_url: None,
+            error_middlewares: Vec::new(),
+            panic_handler: None,
+            middlewares: Vec::new(),
+            canister_id: None,
         }
     }
 
@@ -319,6 +1635,57 @@ impl HttpServe {
             router: r,
             cors_policy: None,
             is_query: created_in_query,
+            field_selection: false,
+            pointer_projection: false,
+            skip_null_fields: false,
+            pretty_print: false,
+            powered_by_version: false,
+            envelope: None,
+            compression: None,
+            max_response_size: None,
+            max_body_size: None,
+            instruction_budget: None,
+            base_url: None,
+            error_middlewares: Vec::new(),
+            panic_handler: None,
+            middlewares: Vec::new(),
+            canister_id: None,
+        }
+    }
+
+    /// Override the principal exposed to handlers through `HttpRequest::canister_id`.
+    /// By default the id of the canister executing the request is used.
+    pub fn set_canister_id(&mut self, canister_id: Principal) {
+        self.canister_id = Some(canister_id);
+    }
+
+    /// Set the canonical base URL of the canister, e.g. `https://example.com` behind a custom domain.
+    /// It is used by `HttpRequest::url_for`, and relative `Location` headers of redirects
+    /// and relative `Link` headers of responses are rewritten to absolute URLs.
+    pub fn base_url(&mut self, base_url: String) {
+        self.base_url = Some(base_url);
+    }
+
+    /// Rewrite relative `Location` URLs of 3xx responses and relative `Link` URLs
+    /// against the base URL, as some gateways mishandle relative redirects.
+    fn absolutize_links(&self, res: &mut HttpResponse) {
+        let base_url = match self.base_url {
+            Some(ref base_url) => base_url,
+            None => return,
+        };
+        let location = res
+            .headers
+            .get("Location")
+            .filter(|_| (300..400).contains(&res.status_code))
+            .filter(|location| location.starts_with('/') && !location.starts_with("//"))
+            .cloned();
+        if let Some(location) = location {
+            res.add_raw_header("Location", join_url(base_url, &location));
+        }
+        let link = res.headers.get("Link").cloned();
+        if let Some(link) = link {
+            let link = link.replace("</", &format!("<{}/", base_url.trim_end_matches('/')));
+            res.add_raw_header("Link", link);
         }
     }
 
@@ -332,7 +1699,7 @@ impl HttpServe {
     pub fn bad_request_error(error: serde_json::Value) -> Result<(), HttpResponse> {
         return Err(HttpResponse {
             status_code: 400,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 400,
                 "message": "Bad Request",
@@ -346,7 +1713,7 @@ impl HttpServe {
     pub fn internal_server_error() -> Result<(), HttpResponse> {
         return Err(HttpResponse {
             status_code: 500,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 500,
                 "message": "Internal server error",
@@ -359,7 +1726,7 @@ impl HttpServe {
     pub fn not_found_error(message: String) -> Result<(), HttpResponse> {
         return Err(HttpResponse {
             status_code: 404,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "statusCode": 404,
                 "message": message,
@@ -369,8 +1736,25 @@ impl HttpServe {
         });
     }
 
+    /// Predefined method not allowed error response.
+    pub fn method_not_allowed_error(message: String) -> Result<(), HttpResponse> {
+        Err(HttpResponse {
+            status_code: 405,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 405,
+                "message": message,
+                "error": "Method Not Allowed"
+            })
+            .into(),
+        })
+    }
+
+    /// Extract the path of the request URL, which may be relative (`/path`),
+    /// absolute (`https://host/path`) or protocol-relative (`//host/path`).
+    /// The query, the fragment and one trailing slash are dropped.
     fn get_path(url: &str) -> &str {
-        let mut path = url.split('?').next().unwrap_or("");
+        let mut path = Self::url_path(url);
         if path.ends_with("/") {
             let mut chars = path.chars();
             chars.next_back();
@@ -379,10 +1763,67 @@ impl HttpServe {
         path
     }
 
+    /// Extract the path of the request URL, keeping a trailing slash.
+    fn url_path(url: &str) -> &str {
+        let url = url.split(['?', '#']).next().unwrap_or("");
+        let authority = match url.split_once("://") {
+            Some((scheme, rest))
+                if !scheme.is_empty()
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) =>
+            {
+                Some(rest)
+            }
+            _ => url.strip_prefix("//"),
+        };
+        match authority {
+            Some(rest) => rest.find('/').map_or("", |index| &rest[index..]),
+            None => url,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn current_canister_id() -> Option<Principal> {
+        Some(ic_cdk::id())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn current_canister_id() -> Option<Principal> {
+        None
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn instruction_counter() -> u64 {
+        ic_cdk::api::instruction_counter()
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), not(test)))]
+    fn instruction_counter() -> u64 {
+        0
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), test))]
+    fn instruction_counter() -> u64 {
+        test::INSTRUCTIONS.with(|instructions| instructions.get())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn trap(message: &str) -> ! {
+        ic_cdk::trap(message)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn trap(message: &str) -> ! {
+        panic!("{}", message)
+    }
+
+    /// Collect the matched params, decoding the `%2F` and `%25` escapes `decode_path` keeps for matching.
     fn params_to_string(params: MatchitParams) -> HashMap<String, String> {
         let mut param: HashMap<String, String> = HashMap::new();
         for val in params.iter() {
-            param.insert(String::from(val.0), String::from(val.1));
+            let value = percent_decode_str(val.1).decode_utf8_lossy();
+            param.insert(String::from(val.0), value.into_owned());
         }
         param
     }
@@ -391,20 +1832,200 @@ impl HttpServe {
         self,
         req: RawHttpRequest,
         path: &str,
-        lookup: Match<'_, '_, &HandlerContainer>,
+        container: &HandlerContainer,
+        params: HashMap<String, String>,
         upgrade: bool,
     ) -> RawHttpResponse {
         let mut req: HttpRequest = req.into();
         req.path = String::from(path);
-        req.params = Self::params_to_string(lookup.params);
-        let handle_res = lookup.value.handler.handle(req).await;
-        let mut res = Self::unwrap_response(handle_res);
-        self.use_res_plugins(&mut res);
-        let mut raw_res: RawHttpResponse = res.into();
+        req.params = params;
+        req.matched_pattern = container.pattern.clone();
+        // The trailing slash dropped for matching belongs to the catch-all remainder
+        if let Some(ref name) = container.catch_all {
+            if Self::url_path(&req.url).ends_with('/') && !path.ends_with('/') {
+                if let Some(rest) = req.params.get_mut(name) {
+                    rest.push('/');
+                }
+            }
+        }
+        req.canister_id = self.canister_id.or_else(Self::current_canister_id);
+        req.base_url = self.base_url.clone();
+        req.max_body_size = self.max_body_size;
+        req.prefix = container.prefix.clone();
+        // Keep the request metadata for the response plugins without copying the body.
+        let body = std::mem::take(&mut req.body);
+        let req_ctx = req.clone();
+        req.body = body;
+        let start = Self::instruction_counter();
+        let mut res = self.run_handler(req, container).await;
+        let budget = container.instruction_budget.or(self.instruction_budget);
+        if let Some(budget) = budget {
+            if Self::instruction_counter().saturating_sub(start) > budget {
+                // The handler already ran: trapping is the only way to roll back what an update call wrote.
+                if !self.is_query {
+                    Self::trap(&format!(
+                        "Handler exceeded the budget of {} instructions",
+                        budget
+                    ));
+                }
+                res = Self::budget_exceeded_error(budget);
+            }
+        }
+        self.router.apply_default_content_type(&mut res);
+        self.use_res_plugins(&req_ctx, Some(container), &mut res);
+        let mut raw_res =
+            self.cap_response_size(Some(container), req_ctx.header("Origin"), res.into());
+        if let Some(ref finalizer) = container.finalizer {
+            finalizer(&mut raw_res.context());
+        }
         raw_res.set_upgrade(upgrade);
         raw_res
     }
 
+    /// Run the global then the route middleware around the handler.
+    async fn run_handler(
+        &self,
+        mut req: HttpRequest,
+        container: &HandlerContainer,
+    ) -> HttpResponse {
+        let middlewares: Vec<&dyn Middleware> = self
+            .middlewares
+            .iter()
+            .chain(container.middlewares.iter())
+            .map(|middleware| middleware.as_ref())
+            .collect();
+        for middleware in middlewares.iter() {
+            if let Err(res) = middleware.before(&mut req) {
+                return res;
+            }
+        }
+        // Keep the request for the `after` hooks without copying the body.
+        let body = std::mem::take(&mut req.body);
+        let req_ctx = req.clone();
+        req.body = body;
+        let res = match catch_unwind(AssertUnwindSafe(|| container.handler.handle(req))) {
+            Ok(future) => CatchUnwind(Box::pin(future)).await,
+            Err(payload) => Err(panic_message(payload)),
+        };
+        let mut res = match res {
+            Ok(res) => Self::unwrap_response(res),
+            Err(message) => match self.panic_handler {
+                Some(ref panic_handler) => panic_handler(&message),
+                None => Self::internal_server_error().unwrap_err(),
+            },
+        };
+        for middleware in middlewares.iter().rev() {
+            middleware.after(&req_ctx, &mut res);
+        }
+        res
+    }
+
+    /// Register a middleware running around the handler of every route, before the route middleware.
+    pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    /// Require a valid bearer JWT on every route.
+    /// The claims returned by `verifier` are available to handlers through `HttpRequest::claims`,
+    /// requests without a valid token get a 401.
+    /// To protect only some routes, pass `Jwt::new(verifier)` to `Router::handle_with` instead.
+    pub fn use_jwt(&mut self, verifier: impl JwtVerifier + 'static) {
+        self.use_middleware(Jwt::new(verifier));
+    }
+
+    /// Replace a response whose body exceeds the configured limit with a 500.
+    fn cap_response_size(
+        &self,
+        container: Option<&HandlerContainer>,
+        origin: Option<&str>,
+        raw_res: RawHttpResponse,
+    ) -> RawHttpResponse {
+        match self.max_response_size {
+            Some(limit) if raw_res.body.len() > limit => {
+                let mut res = HttpResponse {
+                    status_code: 500,
+                    headers: HeaderMap::new(),
+                    body: json!({
+                        "statusCode": 500,
+                        "message": format!("Response body exceeds the limit of {} bytes", limit),
+                        "error": "Internal Server Error"
+                    })
+                    .into(),
+                };
+                self.add_cors_to_res(container, origin, &mut res);
+                self.apply_error_middlewares(&mut res);
+                res.into()
+            }
+            _ => raw_res,
+        }
+    }
+
+    /// Build the response of a handler that panicked with `handler`, instead of the default 500
+    /// without the panic message. The handler gets the panic message, e.g. to log it.
+    ///
+    /// Panics are caught only where the build unwinds on panic, like native builds used in tests.
+    /// Canisters are built for `wasm32-unknown-unknown`, which aborts on panic, so on the IC
+    /// the call still traps and its state changes are rolled back.
+    /// Panics in middleware and response plugins are not caught.
+    pub fn on_panic(&mut self, handler: impl Fn(&str) -> HttpResponse + Send + Sync + 'static) {
+        self.panic_handler = Some(Box::new(handler));
+    }
+
+    fn budget_exceeded_error(budget: u64) -> HttpResponse {
+        HttpResponse {
+            status_code: 500,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 500,
+                "message": format!("Handler exceeded the budget of {} instructions", budget),
+                "error": "Internal Server Error"
+            })
+            .into(),
+        }
+    }
+
+    /// Limit the number of instructions a handler may execute.
+    /// The budget is checked once the handler returns, it does not cut the execution short.
+    /// Query calls going over the budget answer a 500, while update calls trap so the IC rolls back
+    /// their state changes instead of reporting an error for a write that happened.
+    /// The IC counter restarts in every message, so a handler awaiting inter-canister calls is only
+    /// measured from its last `await`.
+    /// `Router::instruction_budget` overrides the budget of a single route.
+    pub fn instruction_budget(&mut self, budget: u64) {
+        self.instruction_budget = Some(budget);
+    }
+
+    /// Limit the size of response bodies produced by handlers, measured after all response plugins.
+    /// Bigger responses are replaced with a 500 instead of trapping at the IC message size limit (about 2 MB).
+    pub fn max_response_size(&mut self, limit: usize) {
+        self.max_response_size = Some(limit);
+    }
+
+    /// Limit the size of request bodies, bigger requests get a 413 before any handler runs.
+    /// The limit is checked in the query call as well as in the update call, so an oversized request
+    /// to a route with `upgrade` enabled is rejected right away and never upgraded to an update call.
+    /// By default request bodies are unlimited.
+    /// The limit also applies to bodies inflated by `HttpRequest::decoded_body`.
+    pub fn max_body_size(&mut self, bytes: usize) {
+        self.max_body_size = Some(bytes);
+    }
+
+    fn payload_too_large(&self, origin: Option<&str>, limit: usize) -> RawHttpResponse {
+        let mut res = HttpResponse {
+            status_code: 413,
+            headers: HeaderMap::new(),
+            body: json!({
+                "statusCode": 413,
+                "message": format!("Request body exceeds the limit of {} bytes", limit),
+                "error": "Payload Too Large"
+            })
+            .into(),
+        };
+        self.add_cors_to_res(None, origin, &mut res);
+        self.apply_error_middlewares(&mut res);
+        res.into()
+    }
+
     fn unwrap_response(res: Result<HttpResponse, HttpResponse>) -> HttpResponse {
         match res {
             Ok(res) => res,
@@ -412,51 +2033,216 @@ impl HttpServe {
         }
     }
 
-    fn use_res_plugins(self, res: &mut HttpResponse) {
-        self.add_cors_to_res(res);
+    fn use_res_plugins(
+        &self,
+        req: &HttpRequest,
+        container: Option<&HandlerContainer>,
+        res: &mut HttpResponse,
+    ) {
+        self.select_fields(req, res);
+        self.project_pointer(req, res);
+        self.strip_null_fields(res);
+        self.absolutize_links(res);
+        if let Some(ref envelope) = self.envelope {
+            envelope.wrap(res);
+        }
+        self.add_cors_to_res(container, req.header("Origin"), res);
+        self.apply_error_middlewares(res);
+        self.pretty_print(req, res);
+        if let Some(ref compression) = self.compression {
+            compression.apply(req, res);
+        }
     }
 
-    fn add_cors_to_res(self, res: &mut HttpResponse) {
-        if let Some(cors) = self.cors_policy {
-            cors.merge(res)
+    fn apply_error_middlewares(&self, res: &mut HttpResponse) {
+        if res.status_code < 400 {
+            return;
+        }
+        for middleware in self.error_middlewares.iter() {
+            middleware(res);
         }
     }
 
-    /// Set the CORS policy of the HttpServe.
-    /// ```rust
-    /// use ic_cdk::{query, update};
-    ///
-    /// use pluto::router::Router;
-    /// use pluto::http_serve;
-    /// use pluto::http::{RawHttpRequest, RawHttpResponse};
-    /// use pluto::http::HttpServe;
-    /// use pluto::method::Method;
-    /// use pluto::cors::Cors;
-    ///
-    /// #[query]
-    /// async fn http_request(req: RawHttpRequest) -> RawHttpResponse {
-    ///     bootstrap(http_serve!(), req).await
-    /// }
-    ///
-    /// #[update]
-    /// async fn http_request_update(req: RawHttpRequest) -> RawHttpResponse {
-    ///     bootstrap(http_serve!(), req).await
-    /// }
-    ///
-    /// async fn bootstrap(mut app: HttpServe, req: RawHttpRequest) -> RawHttpResponse {
-    ///     let router = Router::new();
-    ///     let cors = Cors::new()
-    ///         .allow_origin("*")
-    ///         .allow_methods(vec![Method::POST, Method::PUT])
-    ///         .allow_headers(vec!["Content-Type", "Authorization"])
-    ///         .max_age(Some(3600));
-    ///
-    ///     app.set_router(router);
-    ///     app.use_cors(cors);
-    ///     app.serve(req).await
-    /// }
-    /// ```
-    pub fn use_cors(&mut self, cors_policy: Cors) {
+    /// Register a middleware that runs only on error responses (status code >= 400).
+    /// It is executed in registration order after the other response plugins, CORS included,
+    /// but before pretty printing and compression, so it still works on the plain JSON body.
+    /// This makes it a good place to decorate errors, e.g. with a support URL.
+    pub fn use_error_middleware(
+        &mut self,
+        middleware: impl Fn(&mut HttpResponse) + Send + Sync + 'static,
+    ) {
+        self.error_middlewares.push(Box::new(middleware));
+    }
+
+    fn add_cors_to_res(
+        &self,
+        container: Option<&HandlerContainer>,
+        origin: Option<&str>,
+        res: &mut HttpResponse,
+    ) {
+        if let Some(cors) = self.cors_policy_for(container) {
+            cors.merge_for_origin(origin, res)
+        }
+    }
+
+    /// Get the CORS policy of a route, falling back to the global one.
+    fn cors_policy_for<'a>(&'a self, container: Option<&'a HandlerContainer>) -> Option<&'a Cors> {
+        container
+            .and_then(|container| container.cors.as_ref())
+            .or(self.cors_policy.as_ref())
+    }
+
+    fn select_fields(&self, req: &HttpRequest, res: &mut HttpResponse) {
+        if !self.field_selection {
+            return;
+        }
+        let fields = match req.query_param("fields") {
+            Some(fields) => fields,
+            None => return,
+        };
+        if let HttpBody::Value(Value::Object(ref mut object)) = res.body {
+            let fields: Vec<&str> = fields.split(',').map(str::trim).collect();
+            object.retain(|key, _| fields.contains(&key.as_str()));
+        }
+    }
+
+    fn project_pointer(&self, req: &HttpRequest, res: &mut HttpResponse) {
+        if !self.pointer_projection || !(200..300).contains(&res.status_code) {
+            return;
+        }
+        let pointer = match req.query_param("select") {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        if let HttpBody::Value(ref mut value) = res.body {
+            match value.pointer_mut(&pointer) {
+                Some(projection) => *value = projection.take(),
+                None => {
+                    let message = format!("Cannot select {}", pointer);
+                    *res = Self::not_found_error(message).unwrap_err();
+                }
+            }
+        }
+    }
+
+    fn strip_null_fields(&self, res: &mut HttpResponse) {
+        if !self.skip_null_fields {
+            return;
+        }
+        if let HttpBody::Value(Value::Object(ref mut object)) = res.body {
+            object.retain(|_, value| !value.is_null());
+        }
+    }
+
+    fn pretty_print(&self, req: &HttpRequest, res: &mut HttpResponse) {
+        if !self.pretty_print {
+            return;
+        }
+        let enabled = req.query_param("pretty").is_some_and(|value| {
+            matches!(
+                value.to_ascii_lowercase().as_str(),
+                "" | "1" | "true" | "yes"
+            )
+        });
+        if !enabled {
+            return;
+        }
+        if let HttpBody::Value(ref value) = res.body {
+            if let Ok(pretty) = serde_json::to_vec_pretty(value) {
+                res.body = HttpBody::Raw(pretty);
+            }
+        }
+    }
+
+    /// Enable pretty-printing of JSON responses when the `pretty` query parameter is truthy,
+    /// e.g. `?pretty=1` or `?pretty=true`, for manual API exploration.
+    /// Responses stay compact when the parameter is absent.
+    pub fn use_pretty_print(&mut self, enabled: bool) {
+        self.pretty_print = enabled;
+    }
+
+    /// Include the crate version in the `X-Powered-By` header, e.g. `Pluto/1.2.3`,
+    /// to tell which release a canister runs.
+    pub fn powered_by_version(&mut self, enabled: bool) {
+        self.powered_by_version = enabled;
+    }
+
+    /// Omit `null` top-level fields from JSON object responses instead of serializing them.
+    /// Nested objects and arrays are left untouched.
+    pub fn skip_null_fields(&mut self, enabled: bool) {
+        self.skip_null_fields = enabled;
+    }
+
+    /// Wrap successful JSON responses in the configured envelope.
+    /// Error responses and non-JSON bodies are left as they are.
+    pub fn use_envelope(&mut self, envelope: EnvelopeConfig) {
+        self.envelope = Some(envelope);
+    }
+
+    /// Compress response bodies with gzip when the client accepts it.
+    /// Runs after all other response plugins, so they still see the uncompressed body.
+    pub fn use_compression(&mut self, compression: CompressionConfig) {
+        self.compression = Some(compression);
+    }
+
+    /// Turn the gzip response compression on or off.
+    /// Enabling keeps a config set with `use_compression` and falls back to the default one otherwise.
+    pub fn compression(&mut self, enabled: bool) {
+        self.compression = match enabled {
+            true => Some(self.compression.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// Enable pruning of JSON responses to the fields listed in the `fields` query parameter.
+    /// Only top-level keys of object bodies are filtered, e.g. `?fields=id,name`.
+    /// Responses are left untouched when the parameter is absent.
+    pub fn use_field_selection(&mut self, enabled: bool) {
+        self.field_selection = enabled;
+    }
+
+    /// Enable projection of successful JSON responses to the subtree addressed by the
+    /// JSON Pointer (RFC 6901) in the `select` query parameter, e.g. `?select=/items/0/name`.
+    /// A pointer that does not resolve results in a 404.
+    pub fn use_pointer_projection(&mut self, enabled: bool) {
+        self.pointer_projection = enabled;
+    }
+
+    /// Set the CORS policy of the HttpServe.
+    /// ```rust
+    /// use ic_cdk::{query, update};
+    ///
+    /// use pluto::router::Router;
+    /// use pluto::http_serve;
+    /// use pluto::http::{RawHttpRequest, RawHttpResponse};
+    /// use pluto::http::HttpServe;
+    /// use pluto::method::Method;
+    /// use pluto::cors::Cors;
+    ///
+    /// #[query]
+    /// async fn http_request(req: RawHttpRequest) -> RawHttpResponse {
+    ///     bootstrap(http_serve!(), req).await
+    /// }
+    ///
+    /// #[update]
+    /// async fn http_request_update(req: RawHttpRequest) -> RawHttpResponse {
+    ///     bootstrap(http_serve!(), req).await
+    /// }
+    ///
+    /// async fn bootstrap(mut app: HttpServe, req: RawHttpRequest) -> RawHttpResponse {
+    ///     let router = Router::new();
+    ///     let cors = Cors::new()
+    ///         .allow_origin("*")
+    ///         .allow_methods(vec![Method::POST, Method::PUT])
+    ///         .allow_headers(vec!["Content-Type", "Authorization"])
+    ///         .max_age(Some(3600));
+    ///
+    ///     app.set_router(router);
+    ///     app.use_cors(cors);
+    ///     app.serve(req).await
+    /// }
+    /// ```
+    pub fn use_cors(&mut self, cors_policy: Cors) {
         self.cors_policy = Some(cors_policy);
     }
 
@@ -489,11 +2275,86 @@ impl HttpServe {
     /// }
     /// ```
     pub async fn serve(self, req: RawHttpRequest) -> RawHttpResponse {
+        let powered_by_version = self.powered_by_version;
+        // Only GET responses of query calls are certified, see `certification::certify`
+        let certified_path = (self.is_query && req.method == Method::GET.to_string()).then(|| {
+            percent_decode_str(Self::url_path(&req.url))
+                .decode_utf8_lossy()
+                .into_owned()
+        });
+        let mut res = match self.max_body_size {
+            Some(limit) if req.body.len() > limit => {
+                self.payload_too_large(req.header("Origin"), limit)
+            }
+            _ => self.route(req).await,
+        };
+        if powered_by_version {
+            res.headers.insert(
+                String::from("X-Powered-By"),
+                format!("Pluto/{}", env!("CARGO_PKG_VERSION")),
+            );
+        }
+        if let Some(path) = certified_path {
+            certification::attach(&path, &mut res);
+        }
+        res
+    }
+
+    async fn route(self, req: RawHttpRequest) -> RawHttpResponse {
         match Method::from_str(req.method.as_ref()) {
-            Err(_) => Self::internal_server_error().unwrap_err().into(),
+            // Any valid token is routable, only malformed methods are rejected
+            Err(err) => {
+                let mut res = Self::bad_request_error(json!(err.to_string())).unwrap_err();
+                self.apply_error_middlewares(&mut res);
+                res.into()
+            }
             Ok(method) => {
-                let path = Self::get_path(req.url.as_ref());
-                match self.router.clone().lookup(method, path) {
+                let raw_path = match self.router.trailing_slash {
+                    TrailingSlash::Strict => Self::url_path(req.url.as_ref()),
+                    TrailingSlash::Merge => Self::get_path(req.url.as_ref()),
+                    TrailingSlash::RedirectToNoSlash => {
+                        let url_path = Self::url_path(req.url.as_ref());
+                        if url_path.len() > 1 && url_path.ends_with('/') {
+                            // Leading slashes, backslashes and control characters are collapsed,
+                            // browsers would read `//evil.com` or `/\evil.com` as another site
+                            let path =
+                                url_path
+                                    .trim_end_matches('/')
+                                    .trim_start_matches(|c: char| {
+                                        c == '/' || c == '\\' || c.is_ascii_control()
+                                    });
+                            let path = format!("/{}", path);
+                            let location =
+                                match req.url.split('#').next().unwrap_or("").split_once('?') {
+                                    Some((_, query)) => format!("{}?{}", path, query),
+                                    None => path.to_string(),
+                                };
+                            return HttpResponse::redirect_with_status(308, &location).into();
+                        }
+                        Self::get_path(req.url.as_ref())
+                    }
+                };
+                let path = match decode_path(raw_path) {
+                    Some(path) => path,
+                    None => {
+                        let mut res =
+                            Self::bad_request_error(json!("invalid UTF-8 in the request path"))
+                                .unwrap_err();
+                        self.apply_error_middlewares(&mut res);
+                        return res.into();
+                    }
+                };
+                let path = path.as_str();
+                #[cfg(feature = "dev")]
+                if method == Method::GET {
+                    let req_ctx: HttpRequest = req.clone().into();
+                    if let Some(res) = self.router.dev_routes_response(path, &req_ctx) {
+                        return res.into();
+                    }
+                }
+                // An explicitly registered OPTIONS handler is found here,
+                // so the automatic OPTIONS response below never overrides it.
+                match self.router.clone().lookup(method.clone(), path) {
                     Err(message) => {
                         // Handle OPTIONS request
                         if req.method == Method::OPTIONS.to_string() && self.router.handle_options {
@@ -512,10 +2373,19 @@ impl HttpServe {
                                     None => {
                                         let mut res = HttpResponse {
                                             status_code: 204,
-                                            headers: HashMap::new(),
+                                            headers: HeaderMap::new(),
                                             body: "".to_string().into(),
                                         };
-                                        self.use_res_plugins(&mut res);
+                                        // The preflight gets the CORS policy of the route it asks for
+                                        let route = req
+                                            .header("Access-Control-Request-Method")
+                                            .and_then(|method| Method::from_str(method).ok())
+                                            .and_then(|method| {
+                                                router_clone.lookup(method, path).ok()
+                                            })
+                                            .map(|lookup| lookup.value);
+                                        let req: HttpRequest = req.clone().into();
+                                        self.use_res_plugins(&req, route, &mut res);
                                         if let None =
                                             res.headers.get("Access-Control-Allow-Methods")
                                         {
@@ -524,6 +2394,25 @@ impl HttpServe {
                                                 allow.join(","),
                                             );
                                         }
+                                        // Without configured allow_headers the preflight allows the headers it asks for
+                                        if let Some(requested) =
+                                            req.header("Access-Control-Request-Headers")
+                                        {
+                                            if self.cors_policy_for(route).is_some()
+                                                && !res
+                                                    .headers
+                                                    .contains_key("Access-Control-Allow-Headers")
+                                            {
+                                                res.add_raw_header(
+                                                    "Access-Control-Allow-Headers",
+                                                    requested.to_string(),
+                                                );
+                                                res.append_header(
+                                                    "Vary",
+                                                    "Access-Control-Request-Headers".to_string(),
+                                                );
+                                            }
+                                        }
 
                                         return res.into();
                                     }
@@ -531,7 +2420,110 @@ impl HttpServe {
                             }
                         }
 
-                        return Self::not_found_error(message).unwrap_err().into();
+                        // Without automatic OPTIONS handling known paths may still report their methods
+                        if req.method == Method::OPTIONS.to_string()
+                            && !self.router.handle_options
+                            && self.router.options_allow
+                        {
+                            let allow = self.router.allowed(path);
+                            if !allow.is_empty() {
+                                let mut res = HttpResponse {
+                                    status_code: 204,
+                                    headers: HeaderMap::new(),
+                                    body: "".to_string().into(),
+                                };
+                                res.add_raw_header("Allow", allow.join(", "));
+                                return res.into();
+                            }
+                        }
+
+                        // HEAD is answered by the GET handler without the body
+                        if method == Method::HEAD && self.router.auto_head {
+                            let router = self.router.clone();
+                            if let Ok(lookup) = router.lookup(Method::GET, path) {
+                                let upgrade = lookup.value.upgrade;
+                                if self.is_query && upgrade {
+                                    let mut err: RawHttpResponse =
+                                        Self::internal_server_error().unwrap_err().into();
+                                    err.set_upgrade(upgrade);
+                                    return err;
+                                }
+                                let mut res = self
+                                    .build_and_execute_request(
+                                        req.clone(),
+                                        path,
+                                        lookup.value,
+                                        Self::params_to_string(lookup.params),
+                                        upgrade,
+                                    )
+                                    .await;
+                                // The body of a streamed response is only its first chunk,
+                                // so its length would not be the length of the content
+                                if res.streaming_strategy.is_none() {
+                                    res.headers.insert(
+                                        String::from("Content-Length"),
+                                        res.body.len().to_string(),
+                                    );
+                                }
+                                res.body.clear();
+                                res.streaming_strategy = None;
+                                return res;
+                            }
+                        }
+
+                        // A known path without a handler for the method is a method mismatch, not a missing resource
+                        if method != Method::OPTIONS {
+                            let allow = self.router.allowed(path);
+                            if !allow.is_empty() {
+                                let mut res = Self::method_not_allowed_error(message).unwrap_err();
+                                res.add_raw_header("Allow", allow.join(", "));
+                                // Browsers only surface the error to CORS requests with CORS headers,
+                                // those of the policy of a route registered at the path if any
+                                let route = allow.iter().find_map(|method| {
+                                    let method = Method::from_str(method).ok()?;
+                                    self.router
+                                        .lookup(method, path)
+                                        .ok()
+                                        .map(|lookup| lookup.value)
+                                });
+                                self.add_cors_to_res(route, req.header("Origin"), &mut res);
+                                self.apply_error_middlewares(&mut res);
+                                return res.into();
+                            }
+                        }
+
+                        // Client-side routes of a single-page app are answered with its entry document
+                        if method == Method::GET {
+                            let router = self.router.clone();
+                            if let Some(fallback) = router.lookup_spa_fallback(path, &req) {
+                                return self
+                                    .build_and_execute_request(
+                                        req.clone(),
+                                        path,
+                                        fallback,
+                                        HashMap::new(),
+                                        false,
+                                    )
+                                    .await;
+                            }
+                        }
+
+                        if let Some(ref not_found) = self.router.not_found {
+                            let not_found = not_found.clone();
+                            return self
+                                .build_and_execute_request(
+                                    req.clone(),
+                                    path,
+                                    &not_found,
+                                    HashMap::new(),
+                                    false,
+                                )
+                                .await;
+                        }
+
+                        let mut res = Self::not_found_error(message).unwrap_err();
+                        self.apply_error_middlewares(&mut res);
+                        return res.into();
                     }
                     Ok(lookup) => {
                         let upgrade = lookup.value.upgrade;
@@ -542,7 +2534,13 @@ impl HttpServe {
                             return err;
                         }
                         let res = self
-                            .build_and_execute_request(req.clone(), path, lookup, upgrade)
+                            .build_and_execute_request(
+                                req.clone(),
+                                path,
+                                lookup.value,
+                                Self::params_to_string(lookup.params),
+                                upgrade,
+                            )
                             .await;
                         return res;
                     }
@@ -551,3 +2549,2160 @@ impl HttpServe {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    thread_local! {
+        // Stands in for the IC instruction counter outside of Wasm
+        pub(super) static INSTRUCTIONS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router.get("/user", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({
+                    "id": 1,
+                    "name": "pluto",
+                    "email": "pluto@example.com",
+                })
+                .into(),
+            })
+        });
+        router
+    }
+
+    async fn serve(app: HttpServe, method: &str, url: &str) -> RawHttpResponse {
+        app.serve(RawHttpRequest::test(method, url, &[], &[])).await
+    }
+
+    fn body_json(res: &RawHttpResponse) -> Value {
+        serde_json::from_slice(&res.body).unwrap()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("/search?q=hello+world&tag=a%26b&empty&x=%C3%A9#top"),
+            vec![
+                ("q".to_string(), "hello world".to_string()),
+                ("tag".to_string(), "a&b".to_string()),
+                ("empty".to_string(), "".to_string()),
+                ("x".to_string(), "é".to_string()),
+            ]
+        );
+        assert!(parse_query("/search").is_empty());
+    }
+
+    #[test]
+    fn test_query_vec() {
+        let req: HttpRequest =
+            RawHttpRequest::test("GET", "/items?id=1&sort=asc&id=2&id=3", &[], &[]).into();
+        assert_eq!(req.query_vec::<u64>("id").unwrap(), vec![1, 2, 3]);
+        assert!(req.query_vec::<u64>("missing").unwrap().is_empty());
+
+        let req: HttpRequest =
+            RawHttpRequest::test("GET", "/items?id=1&id=two&id=3", &[], &[]).into();
+        let err = req.query_vec::<u64>("id").unwrap_err();
+        assert_eq!(err.status_code, 400);
+    }
+
+    #[test]
+    fn test_param() {
+        let mut req: HttpRequest = RawHttpRequest::test("GET", "/users/42", &[], &[]).into();
+        req.params = HashMap::from([("id".to_string(), "42".to_string())]);
+        assert_eq!(req.param::<u64>("id").unwrap(), 42);
+        assert_eq!(req.param::<String>("id").unwrap(), "42");
+
+        let err = req.param::<bool>("id").unwrap_err();
+        assert_eq!(err.status_code, 400);
+        assert_eq!(
+            err.body,
+            json!({ "statusCode": 400, "message": "invalid value '42' for 'id'" }).into()
+        );
+        assert_eq!(req.param::<u64>("name").unwrap_err().status_code, 500);
+    }
+
+    #[test]
+    fn test_merge_headers_from() {
+        let mut handler_res = HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([("Content-Type".to_string(), "text/html".to_string())]),
+            body: String::from("<h1>Hello</h1>").into(),
+        };
+        let middleware_res = HttpResponse {
+            status_code: 204,
+            headers: HeaderMap::from([
+                ("content-type".to_string(), "application/json".to_string()),
+                ("X-Request-Id".to_string(), "42".to_string()),
+            ]),
+            body: json!({ "ignored": true }).into(),
+        };
+
+        handler_res.merge_headers_from(&middleware_res);
+        assert_eq!(
+            handler_res.headers,
+            HeaderMap::from([
+                ("Content-Type".to_string(), "text/html".to_string()),
+                ("X-Request-Id".to_string(), "42".to_string()),
+            ])
+        );
+
+        let merged = handler_res.clone().merge(middleware_res.clone());
+        assert_eq!(merged.status_code, 200);
+        assert_eq!(
+            merged.body,
+            HttpBody::String(String::from("<h1>Hello</h1>"))
+        );
+
+        let empty = HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: String::new().into(),
+        };
+        let merged = empty.merge(middleware_res);
+        assert_eq!(merged.status_code, 200);
+        assert_eq!(merged.body, HttpBody::Value(json!({ "ignored": true })));
+        assert_eq!(merged.headers.len(), 2);
+    }
+
+    #[test]
+    fn test_service_unavailable() {
+        let res = HttpResponse::service_unavailable(Some(120));
+        assert_eq!(res.status_code, 503);
+        assert_eq!(res.headers.get("Retry-After").unwrap(), "120");
+        assert_eq!(
+            res.body,
+            json!({
+                "statusCode": 503,
+                "message": "Service Unavailable",
+                "error": "Service Unavailable"
+            })
+            .into()
+        );
+
+        let res = HttpResponse::service_unavailable(None);
+        assert_eq!(res.status_code, 503);
+        assert!(!res.headers.contains_key("Retry-After"));
+    }
+
+    #[test]
+    fn test_add_link() {
+        let mut res = HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: json!([]).into(),
+        };
+        res.add_link("/items?page=3", "next");
+        res.add_link("/items?page=1", "prev");
+        assert_eq!(
+            res.headers.get("Link").unwrap(),
+            r#"</items?page=3>; rel="next", </items?page=1>; rel="prev""#
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Listing {
+            id: String,
+            sort: String,
+            page: Option<String>,
+        }
+
+        let mut req: HttpRequest =
+            RawHttpRequest::test("GET", "/users/42?sort=name&id=7", &[], &[]).into();
+        req.params = HashMap::from([("id".to_string(), "42".to_string())]);
+        assert_eq!(
+            req.extract::<Listing>().unwrap(),
+            Listing {
+                id: "42".to_string(),
+                sort: "name".to_string(),
+                page: None,
+            }
+        );
+
+        let req: HttpRequest = RawHttpRequest::test("GET", "/users?page=1", &[], &[]).into();
+        assert_eq!(req.extract::<Listing>().unwrap_err().status_code, 400);
+    }
+
+    #[test]
+    fn test_params_and_query_value() {
+        let mut req: HttpRequest =
+            RawHttpRequest::test("GET", "/users/42?tag=a&page=2&tag=b%20c&tag=d", &[], &[]).into();
+        req.params = HashMap::from([("id".to_string(), "42".to_string())]);
+        assert_eq!(req.params_value(), json!({ "id": "42" }));
+        assert_eq!(
+            req.query_value(),
+            json!({ "tag": ["a", "b c", "d"], "page": "2" })
+        );
+
+        let req: HttpRequest = RawHttpRequest::test("GET", "/users", &[], &[]).into();
+        assert_eq!(req.params_value(), json!({}));
+        assert_eq!(req.query_value(), json!({}));
+    }
+
+    #[test]
+    fn test_certificate_version() {
+        #[derive(CandidType)]
+        struct LegacyRequest {
+            method: String,
+            url: String,
+            headers: Vec<(String, String)>,
+            body: Vec<u8>,
+        }
+
+        let legacy = candid::encode_one(LegacyRequest {
+            method: "GET".to_string(),
+            url: "/".to_string(),
+            headers: vec![("Host".to_string(), "example.com".to_string())],
+            body: Vec::new(),
+        })
+        .unwrap();
+        let raw: RawHttpRequest = candid::decode_one(&legacy).unwrap();
+        assert_eq!(raw.certificate_version, None);
+        let req: HttpRequest = raw.into();
+        assert_eq!(req.certificate_version, None);
+        assert_eq!(req.header("host"), Some("example.com"));
+
+        let mut raw = RawHttpRequest::test("GET", "/", &[], &[]);
+        raw.certificate_version = Some(2);
+        let raw: RawHttpRequest = candid::decode_one(&candid::encode_one(raw).unwrap()).unwrap();
+        assert_eq!(raw.certificate_version, Some(2));
+        let req: HttpRequest = raw.into();
+        assert_eq!(req.certificate_version, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_headers_end_to_end() {
+        #[derive(CandidType, Deserialize)]
+        struct IcResponse {
+            status_code: u16,
+            headers: Vec<(String, String)>,
+            body: Vec<u8>,
+            upgrade: Option<bool>,
+        }
+
+        let mut router = Router::new();
+        router.get("/login", false, |_req: HttpRequest| async move {
+            let mut headers = HeaderMap::new();
+            headers.append(
+                "Set-Cookie".to_string(),
+                "session=abc; HttpOnly".to_string(),
+            );
+            headers.append("Vary".to_string(), "Accept-Encoding".to_string());
+            headers.append("Set-Cookie".to_string(), "theme=dark".to_string());
+            headers.append("X-Custom".to_string(), "1".to_string());
+            Ok(HttpResponse {
+                status_code: 200,
+                headers,
+                body: json!({}).into(),
+            })
+        });
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "GET", "/login").await;
+
+        let expected = vec![
+            (
+                "Set-Cookie".to_string(),
+                "session=abc; HttpOnly".to_string(),
+            ),
+            ("Vary".to_string(), "Accept-Encoding".to_string()),
+            ("Set-Cookie".to_string(), "theme=dark".to_string()),
+            ("X-Custom".to_string(), "1".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("X-Powered-By".to_string(), "Pluto".to_string()),
+        ];
+        assert_eq!(Vec::<(String, String)>::from(res.headers.clone()), expected);
+
+        let ic: IcResponse = candid::decode_one(&candid::encode_one(res).unwrap()).unwrap();
+        assert_eq!(ic.status_code, 200);
+        assert_eq!(ic.headers, expected);
+    }
+
+    #[tokio::test]
+    async fn test_json_bytes() {
+        let bytes = br#"{ "b": 1, "a": [1.50] }"#.to_vec();
+        let res = HttpResponse::json_bytes(202, bytes.clone());
+        assert_eq!(res.status_code, 202);
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "application/json");
+
+        let mut router = Router::new();
+        router.get("/cached", false, move |_req: HttpRequest| {
+            let res = HttpResponse::json_bytes(200, bytes.clone());
+            async move { Ok(res) }
+        });
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "GET", "/cached").await;
+        assert_eq!(res.body, br#"{ "b": 1, "a": [1.50] }"#.to_vec());
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "application/json");
+    }
+
+    #[test]
+    fn test_redirect() {
+        let res = HttpResponse::redirect("/login");
+        assert_eq!(res.status_code, 302);
+        assert_eq!(res.headers.get("Location").unwrap(), "/login");
+        assert_eq!(res.body, HttpBody::String(String::new()));
+        assert_eq!(HttpResponse::permanent_redirect("/new").status_code, 301);
+        let res = HttpResponse::redirect_with_status(303, "/orders/1");
+        assert_eq!(res.status_code, 303);
+        assert_eq!(res.headers.get("Location").unwrap(), "/orders/1");
+    }
+
+    #[test]
+    fn test_with_etag() {
+        let request = |if_none_match: Option<&str>| -> HttpRequest {
+            let headers: Vec<(&str, &str)> = if_none_match
+                .map(|value| ("If-None-Match", value))
+                .into_iter()
+                .collect();
+            RawHttpRequest::test("GET", "/app.css", &headers, &[]).into()
+        };
+        let response = || {
+            let mut res = HttpResponse::css("body {}".to_string());
+            res.add_raw_header("Cache-Control", "public, max-age=60".to_string());
+            res
+        };
+
+        let res = response().with_etag(&request(None));
+        assert_eq!(res.status_code, 200);
+        let etag = res.headers.get("ETag").unwrap().clone();
+        assert_eq!(etag, "\"7-a9b342a0ff2b8953\"");
+        assert_eq!(res.body, HttpBody::String("body {}".to_string()));
+
+        for if_none_match in [
+            etag.clone(),
+            format!("\"other\", W/{}", etag),
+            "*".to_string(),
+        ] {
+            let res = response().with_etag(&request(Some(&if_none_match)));
+            assert_eq!(res.status_code, 304);
+            assert_eq!(res.headers.get("ETag").unwrap(), &etag);
+            assert_eq!(
+                res.headers.get("Cache-Control").unwrap(),
+                "public, max-age=60"
+            );
+            assert_eq!(res.headers.get("Content-Type"), None);
+            assert_eq!(res.body, HttpBody::Raw(Vec::new()));
+        }
+        let res = response().with_etag(&request(Some("\"other\"")));
+        assert_eq!(res.status_code, 200);
+        let res = HttpResponse::no_content().with_etag(&request(Some("*")));
+        assert_eq!(res.status_code, 204);
+        assert_eq!(res.headers.get("ETag"), None);
+    }
+
+    #[test]
+    fn test_csv() {
+        let rows = vec![
+            vec!["1".to_string(), "Doe, John".to_string()],
+            vec!["2".to_string(), "say \"hi\"".to_string()],
+            vec!["3".to_string(), "line\nbreak".to_string()],
+            vec!["4".to_string(), String::new()],
+        ];
+        let res = HttpResponse::csv("users \"all\".csv", &["id", "name"], rows);
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(
+            res.headers.get("Content-Disposition").unwrap(),
+            "attachment; filename=\"users \\\"all\\\".csv\""
+        );
+        assert_eq!(
+            res.body,
+            HttpBody::Raw(
+                b"id,name\r\n1,\"Doe, John\"\r\n2,\"say \"\"hi\"\"\"\r\n3,\"line\nbreak\"\r\n4,\r\n".to_vec()
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expect a 3xx redirect status code, found: 200")]
+    fn test_redirect_invalid_status() {
+        HttpResponse::redirect_with_status(200, "/");
+    }
+
+    #[test]
+    fn test_json() {
+        #[derive(Serialize)]
+        struct User {
+            id: u64,
+            name: &'static str,
+        }
+        let res = HttpResponse::json(
+            201,
+            &User {
+                id: 1,
+                name: "pluto",
+            },
+        );
+        assert_eq!(res.status_code, 201);
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "application/json");
+        assert_eq!(
+            res.body,
+            HttpBody::Raw(br#"{"id":1,"name":"pluto"}"#.to_vec())
+        );
+
+        let invalid = HashMap::from([(vec![1u8], 1)]);
+        assert_eq!(HttpResponse::json(200, &invalid).status_code, 500);
+    }
+
+    #[test]
+    fn test_response_builder() {
+        let res = HttpResponse::builder()
+            .status(201)
+            .header("X-Foo", "bar")
+            .json(json!({ "id": 1 }))
+            .build();
+        assert_eq!(
+            res,
+            HttpResponse {
+                status_code: 201,
+                headers: HeaderMap::from([
+                    ("X-Foo".to_string(), "bar".to_string()),
+                    ("Content-Type".to_string(), "application/json".to_string()),
+                ]),
+                body: json!({ "id": 1 }).into(),
+            }
+        );
+
+        let res = HttpResponse::builder().text("hello".to_string()).build();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.body, HttpBody::String("hello".to_string()));
+
+        let res = HttpResponse::builder()
+            .raw(vec![1, 2])
+            .header("Content-Type", "image/png")
+            .build();
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "image/png");
+        assert_eq!(res.body, HttpBody::Raw(vec![1, 2]));
+
+        let invalid = HashMap::from([(vec![1u8], 1)]);
+        let res = HttpResponse::builder().json(invalid).build();
+        assert_eq!(res.status_code, 500);
+    }
+
+    #[test]
+    fn test_append_header() {
+        let mut res = HttpResponse::no_content();
+        res.append_header("Vary", "Origin".to_string());
+        res.append_header("vary", "Accept-Encoding".to_string());
+        assert_eq!(
+            res.headers.get_all("Vary").collect::<Vec<_>>(),
+            vec!["Origin", "Accept-Encoding"]
+        );
+
+        // Setting a header replaces every value, removing it drops them all
+        res.add_raw_header("Vary", "Accept".to_string());
+        assert_eq!(
+            res.headers.get_all("Vary").collect::<Vec<_>>(),
+            vec!["Accept"]
+        );
+        res.append_header("Vary", "Origin".to_string());
+        res.remove_header("VARY");
+        assert!(res.headers.is_empty());
+    }
+
+    #[test]
+    fn test_header_map() {
+        let mut headers = HeaderMap::from([
+            ("Set-Cookie".to_string(), "a=1".to_string()),
+            ("Vary".to_string(), "Origin".to_string()),
+            ("set-cookie".to_string(), "b=2".to_string()),
+        ]);
+        assert_eq!(headers.get("SET-COOKIE").unwrap(), "a=1");
+        assert_eq!(
+            headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+
+        assert_eq!(
+            headers.insert("Set-Cookie".to_string(), "c=3".to_string()),
+            Some("a=1".to_string())
+        );
+        assert_eq!(
+            Vec::<(String, String)>::from(headers.clone()),
+            vec![
+                ("Set-Cookie".to_string(), "c=3".to_string()),
+                ("Vary".to_string(), "Origin".to_string()),
+            ]
+        );
+
+        assert_eq!(headers.remove("vary"), Some("Origin".to_string()));
+        assert_eq!(headers.len(), 1);
+        assert!(!headers.contains_key("Vary"));
+    }
+
+    #[cfg(feature = "dev")]
+    #[tokio::test]
+    async fn test_dev_routes_page() {
+        let mut router = router();
+        router.dev_routes_page("/__routes");
+        router.post("/users/{id}", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse::no_content())
+        });
+
+        let app = HttpServe::new_with_router(router.clone(), "http_request");
+        let res = serve(app, "GET", "/__routes").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            body_json(&res),
+            json!([
+                { "method": "GET", "path": "/user" },
+                { "method": "POST", "path": "/users/{id}" },
+            ])
+        );
+
+        let app = HttpServe::new_with_router(router, "http_request");
+        let raw = RawHttpRequest::test("GET", "/__routes", &[("Accept", "text/html")], &[]);
+        let res = app.serve(raw).await;
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        let html = String::from_utf8(res.body).unwrap();
+        assert!(html.contains("<tr><td>GET</td><td>/user</td></tr>"));
+        assert!(html.contains("<tr><td>POST</td><td>/users/{id}</td></tr>"));
+    }
+
+    #[tokio::test]
+    async fn test_base_url() {
+        let mut router = Router::new();
+        router.get("/items", false, |req: HttpRequest| async move {
+            let mut res =
+                crate::pagination::Paginated::new(vec![1], 1, 1, 2).into_response("/items");
+            res.add_raw_header("X-Self", req.url_for("/items"));
+            Ok(res)
+        });
+        router.get("/old", false, |_req: HttpRequest| async move {
+            let mut res = HttpResponse::no_content();
+            res.status_code = 302;
+            res.add_raw_header("Location", "/new".to_string());
+            Ok(res)
+        });
+
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.base_url("https://example.com/".to_string());
+            app
+        };
+        let res = serve(app(), "GET", "/items").await;
+        assert_eq!(
+            res.headers.get("X-Self").unwrap(),
+            "https://example.com/items"
+        );
+        assert_eq!(
+            res.headers.get("Link").unwrap(),
+            "<https://example.com/items?page=1&per_page=1>; rel=\"first\", \
+             <https://example.com/items?page=2&per_page=1>; rel=\"next\", \
+             <https://example.com/items?page=2&per_page=1>; rel=\"last\""
+        );
+        let res = serve(app(), "GET", "/old").await;
+        assert_eq!(
+            res.headers.get("Location").unwrap(),
+            "https://example.com/new"
+        );
+
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "GET", "/old").await;
+        assert_eq!(res.headers.get("Location").unwrap(), "/new");
+    }
+
+    #[tokio::test]
+    async fn test_absolute_location() {
+        let mut router = Router::new();
+        for (path, status_code, location) in [
+            ("/moved", 301, "/new"),
+            ("/external", 307, "//cdn.example.com/new"),
+            ("/created", 201, "/items/1"),
+        ] {
+            router.get(path, false, move |_req: HttpRequest| async move {
+                let mut res = HttpResponse::no_content();
+                res.status_code = status_code;
+                res.add_raw_header("Location", location.to_string());
+                Ok(res)
+            });
+        }
+        let app = |base_url: Option<&str>| {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            if let Some(base_url) = base_url {
+                app.base_url(base_url.to_string());
+            }
+            app
+        };
+
+        let location = |res: RawHttpResponse| res.headers.get("Location").unwrap().clone();
+        let base_url = Some("https://example.com");
+        assert_eq!(
+            location(serve(app(base_url), "GET", "/moved").await),
+            "https://example.com/new"
+        );
+        assert_eq!(
+            location(serve(app(base_url), "GET", "/external").await),
+            "//cdn.example.com/new"
+        );
+        assert_eq!(
+            location(serve(app(base_url), "GET", "/created").await),
+            "/items/1"
+        );
+        assert_eq!(location(serve(app(None), "GET", "/moved").await), "/new");
+    }
+
+    #[test]
+    fn test_url_for() {
+        let req: HttpRequest = RawHttpRequest::test("GET", "/", &[], &[]).into();
+        assert_eq!(req.url_for("/items"), "/items");
+        let req: HttpRequest =
+            RawHttpRequest::test("GET", "/", &[("host", "abc.icp0.io")], &[]).into();
+        assert_eq!(req.url_for("/items"), "https://abc.icp0.io/items");
+        assert_eq!(req.url_for("https://other.io/x"), "https://other.io/x");
+    }
+
+    #[tokio::test]
+    async fn test_pointer_projection() {
+        let mut router = Router::new();
+        router.get("/orders", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "data": { "items": [{ "name": "apple" }, { "name": "pear" }] } })
+                    .into(),
+            })
+        });
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.use_pointer_projection(true);
+            app
+        };
+
+        let res = serve(app(), "GET", "/orders?select=/data/items/1/name").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(body_json(&res), json!("pear"));
+        let res = serve(app(), "GET", "/orders?select=/data/items/0").await;
+        assert_eq!(body_json(&res), json!({ "name": "apple" }));
+
+        let res = serve(app(), "GET", "/orders?select=/data/missing").await;
+        assert_eq!(res.status_code, 404);
+        assert_eq!(body_json(&res)["message"], "Cannot select /data/missing");
+
+        let res = serve(app(), "GET", "/orders").await;
+        assert_eq!(body_json(&res)["data"]["items"][0]["name"], "apple");
+    }
+
+    #[tokio::test]
+    async fn test_relative_path() {
+        let mut router = Router::new();
+        let handler = |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "path": req.path, "relative": req.relative_path() }).into(),
+            })
+        };
+        router.get("/health", false, handler);
+        router.set_global_prefix("/api".to_string());
+        router.get("/users/{id}", false, handler);
+        router.get("/", false, handler);
+
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+        let res = serve(app(), "GET", "/api/users/7").await;
+        assert_eq!(
+            body_json(&res),
+            json!({ "path": "/api/users/7", "relative": "/users/7" })
+        );
+        let res = serve(app(), "GET", "/api").await;
+        assert_eq!(body_json(&res), json!({ "path": "/api", "relative": "/" }));
+        let res = serve(app(), "GET", "/health").await;
+        assert_eq!(
+            body_json(&res),
+            json!({ "path": "/health", "relative": "/health" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_instruction_budget() {
+        let work = |instructions: u64| {
+            move |_req: HttpRequest| async move {
+                INSTRUCTIONS.with(|counter| counter.set(counter.get() + instructions));
+                Ok(HttpResponse::no_content())
+            }
+        };
+        let mut router = Router::new();
+        router.get("/slow", false, work(5_000));
+        router.get("/report", false, work(5_000));
+        router.get("/fast", false, work(500));
+        router.instruction_budget(Method::GET, "/slow", 10_000);
+        router.instruction_budget(Method::GET, "/fast", 100);
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.instruction_budget(1_000);
+            app
+        };
+
+        assert_eq!(serve(app(), "GET", "/slow").await.status_code, 204);
+        let res = serve(app(), "GET", "/report").await;
+        assert_eq!(res.status_code, 500);
+        assert_eq!(
+            body_json(&res)["message"],
+            "Handler exceeded the budget of 1000 instructions"
+        );
+        let res = serve(app(), "GET", "/fast").await;
+        assert_eq!(res.status_code, 500);
+        assert_eq!(
+            body_json(&res)["message"],
+            "Handler exceeded the budget of 100 instructions"
+        );
+        let app = HttpServe::new_with_router(router.clone(), "http_request");
+        assert_eq!(serve(app, "GET", "/report").await.status_code, 204);
+
+        let mut app = HttpServe::new_with_router(router.clone(), "http_request_update");
+        app.instruction_budget(1_000);
+        let res = crate::panic::CatchUnwind(Box::pin(serve(app, "GET", "/report"))).await;
+        assert_eq!(
+            res.err().as_deref(),
+            Some("Handler exceeded the budget of 1000 instructions")
+        );
+    }
+
+    #[test]
+    fn test_content_negotiation() {
+        let request = |accept: Option<&str>| -> HttpRequest {
+            let headers: Vec<(&str, &str)> = accept
+                .map(|accept| ("Accept", accept))
+                .into_iter()
+                .collect();
+            RawHttpRequest::test("GET", "/", &headers, &[]).into()
+        };
+        let offered = ["application/json", "text/html"];
+
+        let req = request(Some("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert_eq!(req.preferred_content_type(&offered), Some("text/html"));
+        assert!(req.accepts("image/png"));
+        let req = request(Some("application/json"));
+        assert_eq!(
+            req.preferred_content_type(&offered),
+            Some("application/json")
+        );
+        assert!(!req.accepts("text/html"));
+        let req = request(Some("image/*"));
+        assert_eq!(req.preferred_content_type(&offered), None);
+        let req = request(None);
+        assert_eq!(
+            req.preferred_content_type(&offered),
+            Some("application/json")
+        );
+        assert!(req.accepts("text/csv"));
+    }
+
+    #[tokio::test]
+    async fn test_decoded_body() {
+        let json = br#"{"name":"pluto"}"#;
+        let gzip = CompressionConfig::new().compress(json);
+        let request = |encoding: Option<&str>, body: &[u8]| -> HttpRequest {
+            let headers: Vec<(&str, &str)> = encoding
+                .map(|encoding| ("Content-Encoding", encoding))
+                .into_iter()
+                .collect();
+            RawHttpRequest::test("POST", "/", &headers, body).into()
+        };
+
+        assert_eq!(request(Some("gzip"), &gzip).decoded_body().unwrap(), json);
+        assert_eq!(request(None, json).decoded_body().unwrap(), json);
+        let err = request(Some("gzip"), b"not gzip")
+            .decoded_body()
+            .unwrap_err();
+        assert_eq!(err.status_code, 400);
+        let err = request(Some("br"), json).decoded_body().unwrap_err();
+        assert_eq!(err.status_code, 415);
+
+        // The limit of the HttpServe applies to the inflated body
+        let bomb = CompressionConfig::new().compress(&[0; 4096]);
+        let mut router = Router::new();
+        router.post("/", false, |req: HttpRequest| async move {
+            let body = req.decoded_body()?;
+            Ok(HttpResponse::json(200, &body.len()))
+        });
+        let mut app = HttpServe::new_with_router(router, "http_request");
+        app.max_body_size(1024);
+        let headers = [("Content-Encoding", "gzip")];
+        let res = app
+            .serve(RawHttpRequest::test("POST", "/", &headers, &bomb))
+            .await;
+        assert_eq!(res.status_code, 413);
+    }
+
+    #[test]
+    fn test_multipart() {
+        let body = b"--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\
+            \r\n\
+            Holiday\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"photo\"; filename=\"beach.png\"\r\n\
+            Content-Type: image/png\r\n\
+            \r\n\
+            \x89PNG\r\n\
+            --XyZ--\r\n";
+        let request = |content_type: &str| -> HttpRequest {
+            RawHttpRequest::test("POST", "/", &[("Content-Type", content_type)], body).into()
+        };
+
+        let parts = request("multipart/form-data; boundary=XyZ")
+            .multipart()
+            .unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name(), Some(String::from("title")));
+        assert_eq!(parts[0].data, b"Holiday");
+        assert_eq!(parts[1].filename(), Some(String::from("beach.png")));
+        assert_eq!(parts[1].content_type(), "image/png");
+        assert_eq!(parts[1].data, b"\x89PNG");
+
+        let err = request("multipart/form-data").multipart().unwrap_err();
+        assert_eq!(err.status_code, 400);
+        let err = request("multipart/form-data; boundary=other")
+            .multipart()
+            .unwrap_err();
+        assert_eq!(err.status_code, 400);
+    }
+
+    #[tokio::test]
+    async fn test_max_body_size() {
+        let mut router = Router::new();
+        router.post("/upload", true, |req: HttpRequest| async move {
+            Ok(HttpResponse::json_bytes(200, req.body))
+        });
+        let app = |is_query: bool| {
+            let init_name = if is_query {
+                "http_request"
+            } else {
+                "http_request_update"
+            };
+            let mut app = HttpServe::new_with_router(router.clone(), init_name);
+            app.max_body_size(4);
+            app
+        };
+
+        let res = app(false)
+            .serve(RawHttpRequest::test("POST", "/upload", &[], b"1234"))
+            .await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.body, b"1234".to_vec());
+        for is_query in [true, false] {
+            let res = app(is_query)
+                .serve(RawHttpRequest::test("POST", "/upload", &[], b"12345"))
+                .await;
+            assert_eq!(res.status_code, 413);
+            assert_eq!(res.upgrade, Some(false));
+            assert_eq!(
+                body_json(&res)["message"],
+                "Request body exceeds the limit of 4 bytes"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_response_size() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.max_response_size(1024);
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(body_json(&res)["name"], "pluto");
+
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.max_response_size(16);
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(res.status_code, 500);
+        assert_eq!(
+            body_json(&res)["message"],
+            "Response body exceeds the limit of 16 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_default_content_type() {
+        let mut router = router();
+        router.default_content_type("text/html");
+        router.get("/page", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: String::from("<h1>Hello</h1>").into(),
+            })
+        });
+        router.get("/feed", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::from([(
+                    "Content-Type".to_string(),
+                    "application/rss+xml".to_string(),
+                )]),
+                body: String::from("<rss/>").into(),
+            })
+        });
+
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+        let res = serve(app(), "GET", "/page").await;
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "text/html");
+        let res = serve(app(), "GET", "/feed").await;
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "application/rss+xml"
+        );
+        let res = serve(app(), "GET", "/user").await;
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "application/json");
+    }
+
+    #[test]
+    fn test_json_with() {
+        let value = json!({ "b": 1, "a": { "d": 2, "c": 3 } });
+        let res = HttpResponse::json_with(&value, &JsonConfig::new().sort_keys(true)).unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "application/json");
+        assert_eq!(
+            Vec::<u8>::from(res.body),
+            br#"{"a":{"c":3,"d":2},"b":1}"#.to_vec()
+        );
+
+        let invalid = HashMap::from([((1, 2), "tuple keys are not valid JSON")]);
+        let res = HttpResponse::json_with(&invalid, &JsonConfig::new()).unwrap_err();
+        assert_eq!(res.status_code, 500);
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": { "name": { "type": "string" }, "age": { "type": "integer" } }
+        });
+        let request =
+            |body: &[u8]| -> HttpRequest { RawHttpRequest::test("POST", "/", &[], body).into() };
+
+        assert!(request(br#"{"name":"pluto","age":3}"#)
+            .validate_schema(&schema)
+            .is_ok());
+
+        let res = request(br#"{"age":"3"}"#)
+            .validate_schema(&schema)
+            .unwrap_err();
+        assert_eq!(res.status_code, 422);
+        let body = match res.body {
+            HttpBody::Value(body) => body,
+            _ => panic!("expected a JSON body"),
+        };
+        assert_eq!(
+            body["errors"],
+            json!([
+                { "path": "/name", "message": "is required" },
+                { "path": "/age", "message": "expected integer, found string" },
+            ])
+        );
+
+        let res = request(b"not json").validate_schema(&schema).unwrap_err();
+        assert_eq!(res.status_code, 400);
+    }
+
+    #[tokio::test]
+    async fn test_accepted_job() {
+        let mut router = Router::new();
+        router.post("/reports", false, |req: HttpRequest| async move {
+            let payload: Value = req.body_into_struct()?;
+            let id = crate::jobs::enqueue("report", payload);
+            Ok(HttpResponse::accepted(&format!("/jobs/{}", id)))
+        });
+        let app = HttpServe::new_with_router(router, "http_request_update");
+        let raw = RawHttpRequest::test("POST", "/reports", &[], br#"{"month":3}"#);
+        let res = app.serve(raw).await;
+
+        let job = crate::jobs::next().unwrap();
+        assert_eq!(job.kind, "report");
+        assert_eq!(job.payload, json!({ "month": 3 }));
+        let location = format!("/jobs/{}", job.id);
+        assert_eq!(res.status_code, 202);
+        assert_eq!(res.headers.get("Location").unwrap(), &location);
+        assert_eq!(body_json(&res)["location"], location);
+    }
+
+    #[test]
+    fn test_asset_responses() {
+        let res = HttpResponse::css(String::from("body { margin: 0 }"));
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/css; charset=utf-8"
+        );
+        assert_eq!(
+            res.body,
+            HttpBody::String(String::from("body { margin: 0 }"))
+        );
+
+        let res = HttpResponse::javascript(String::from("console.log(1)"));
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/javascript; charset=utf-8"
+        );
+
+        let res = HttpResponse::svg(String::from("<svg/>"));
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "image/svg+xml");
+    }
+
+    #[test]
+    fn test_protobuf() {
+        let res = HttpResponse::protobuf(vec![0x08, 0x96, 0x01]);
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "application/x-protobuf"
+        );
+        assert_eq!(res.body, HttpBody::Raw(vec![0x08, 0x96, 0x01]));
+
+        let request = |content_type: &str| -> HttpRequest {
+            RawHttpRequest::test(
+                "POST",
+                "/",
+                &[("Content-Type", content_type)],
+                &[0x08, 0x01],
+            )
+            .into()
+        };
+        assert_eq!(
+            request("application/x-protobuf").protobuf_body().unwrap(),
+            &[0x08, 0x01]
+        );
+        assert!(request("application/grpc-web+proto")
+            .protobuf_body()
+            .is_ok());
+        let res = request("application/json").protobuf_body().unwrap_err();
+        assert_eq!(res.status_code, 415);
+    }
+
+    #[test]
+    fn test_no_content() {
+        let res = HttpResponse::no_content();
+        assert_eq!(res.status_code, 204);
+        assert!(res.headers.is_empty());
+        assert_eq!(Vec::<u8>::from(res.body), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_require_if_match() {
+        let request = |if_match: Option<&str>| -> HttpRequest {
+            let headers: Vec<(&str, &str)> =
+                if_match.map(|v| ("If-Match", v)).into_iter().collect();
+            RawHttpRequest::test("DELETE", "/items/1", &headers, &[]).into()
+        };
+
+        assert!(request(None).require_if_match(Some("\"v1\"")).is_ok());
+        assert!(request(None).require_if_match(None).is_ok());
+        assert!(request(Some("\"v0\", \"v1\""))
+            .require_if_match(Some("\"v1\""))
+            .is_ok());
+        assert!(request(Some("*")).require_if_match(Some("\"v1\"")).is_ok());
+
+        let res = request(Some("\"v0\""))
+            .require_if_match(Some("\"v1\""))
+            .unwrap_err();
+        assert_eq!(res.status_code, 412);
+        let res = request(Some("W/\"v1\""))
+            .require_if_match(Some("\"v1\""))
+            .unwrap_err();
+        assert_eq!(res.status_code, 412);
+        let res = request(Some("*")).require_if_match(None).unwrap_err();
+        assert_eq!(res.status_code, 412);
+    }
+
+    #[test]
+    fn test_require_content_type() {
+        let req: HttpRequest = RawHttpRequest::test(
+            "POST",
+            "/",
+            &[("content-type", "Application/JSON; charset=utf-8")],
+            &[],
+        )
+        .into();
+        assert!(req.require_content_type("application/json").is_ok());
+
+        let req: HttpRequest =
+            RawHttpRequest::test("POST", "/", &[("Content-Type", "text/plain")], &[]).into();
+        let err = req.require_content_type("application/json").unwrap_err();
+        assert_eq!(err.status_code, 415);
+
+        let req: HttpRequest = RawHttpRequest::test("POST", "/", &[], &[]).into();
+        assert_eq!(
+            req.require_content_type("application/json")
+                .unwrap_err()
+                .status_code,
+            415
+        );
+    }
+
+    #[test]
+    fn test_decode_body() {
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct Point {
+            x: i32,
+            label: String,
+        }
+        let point = Point {
+            x: 3,
+            label: "a".to_string(),
+        };
+        let request = |content_type: &str, body: &[u8]| -> HttpRequest {
+            RawHttpRequest::test("POST", "/", &[("Content-Type", content_type)], body).into()
+        };
+
+        let json = serde_json::to_vec(&point).unwrap();
+        assert_eq!(
+            request("application/json", &json).decode_body::<Point>(),
+            Ok(point.clone())
+        );
+        let res = request("application/json", b"{").decode_body::<Point>();
+        assert_eq!(res.unwrap_err().status_code, 400);
+
+        // The same struct from MessagePack, with named and compact (array) encodings
+        #[cfg(feature = "msgpack")]
+        {
+            let named = rmp_serde::to_vec_named(&point).unwrap();
+            let compact = rmp_serde::to_vec(&point).unwrap();
+            assert_eq!(
+                request("application/msgpack", &named).decode_body::<Point>(),
+                Ok(point.clone())
+            );
+            assert_eq!(
+                request("application/x-msgpack", &compact).decode_body::<Point>(),
+                Ok(point)
+            );
+            let res = request("application/vnd.msgpack", b"{}").decode_body::<Point>();
+            assert_eq!(res.unwrap_err().status_code, 400);
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let req: HttpRequest = RawHttpRequest::test(
+            "GET",
+            "/",
+            &[
+                ("Content-Type", "application/json"),
+                ("Accept", "text/html"),
+                ("accept", "application/json"),
+            ],
+            &[],
+        )
+        .into();
+        assert_eq!(req.header("content-type"), Some("application/json"));
+        assert_eq!(req.header("CONTENT-TYPE"), Some("application/json"));
+        assert_eq!(req.header("Authorization"), None);
+        assert_eq!(
+            req.headers_all("Accept"),
+            vec!["text/html", "application/json"]
+        );
+        assert!(req.headers_all("Cookie").is_empty());
+    }
+
+    #[test]
+    fn test_method_enum() {
+        let method = |method: &str| {
+            HttpRequest::from(RawHttpRequest::test(method, "/", &[], &[])).method_enum()
+        };
+        assert_eq!(method("DELETE"), Some(Method::DELETE));
+        assert_eq!(
+            method("PROPFIND"),
+            Some(Method::from_str("PROPFIND").unwrap())
+        );
+        assert_ne!(method("get"), Some(Method::GET));
+        assert_eq!(method(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_cookie() {
+        let mut router = Router::new();
+        router.post("/login", false, |_req: HttpRequest| async move {
+            let mut res = HttpResponse::no_content();
+            res.set_cookie(Cookie::new("session", "abc").http_only(true));
+            res.set_cookie(Cookie::new("theme", "dark").path("/"));
+            Ok(res)
+        });
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "POST", "/login").await;
+        let cookies: Vec<&String> = res.headers.get_all("Set-Cookie").collect();
+        assert_eq!(cookies, vec!["session=abc; HttpOnly", "theme=dark; Path=/"]);
+    }
+
+    #[test]
+    fn test_cookies() {
+        let req: HttpRequest = RawHttpRequest::test(
+            "GET",
+            "/",
+            &[(
+                "Cookie",
+                "session=abc%20123; theme = dark;malformed; =empty; quoted=\"x\"",
+            )],
+            &[],
+        )
+        .into();
+        let cookies = req.cookies();
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies.get("theme").unwrap(), "dark");
+        assert_eq!(cookies.get("quoted").unwrap(), "x");
+        assert_eq!(req.cookie("session"), Some(String::from("abc 123")));
+        assert_eq!(req.cookie("malformed"), None);
+
+        let req: HttpRequest = RawHttpRequest::test("GET", "/", &[], &[]).into();
+        assert!(req.cookies().is_empty());
+        assert_eq!(req.cookie("session"), None);
+    }
+
+    #[test]
+    fn test_query() {
+        #[derive(Debug, Deserialize)]
+        struct Search {
+            q: String,
+            page: String,
+        }
+        let req: HttpRequest = RawHttpRequest::test(
+            "GET",
+            "/search?q=caf%C3%A9+au+lait&page=2&page=3&a%26b=1",
+            &[],
+            &[],
+        )
+        .into();
+        assert_eq!(req.query.get("q").unwrap(), "café au lait");
+        assert_eq!(req.query.get("page").unwrap(), "2");
+        assert_eq!(req.query.get("a&b").unwrap(), "1");
+        let search: Search = req.query_into_struct().unwrap();
+        assert_eq!(
+            (search.q.as_str(), search.page.as_str()),
+            ("café au lait", "2")
+        );
+
+        let req: HttpRequest = RawHttpRequest::test("GET", "/search", &[], &[]).into();
+        assert!(req.query.is_empty());
+        assert_eq!(
+            req.query_into_struct::<Search>().unwrap_err().status_code,
+            400
+        );
+    }
+
+    #[tokio::test]
+    async fn test_catch_all() {
+        let mut router = Router::new();
+        router.get("/files/{*path}", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!(req.params).into(),
+            })
+        });
+        router.get("/assets/*rest", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!(req.params).into(),
+            })
+        });
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "GET", "/files/docs/2024/report.pdf?download=1").await;
+        assert_eq!(body_json(&res), json!({ "path": "docs/2024/report.pdf" }));
+        let res = serve(app(), "GET", "/files/docs/").await;
+        assert_eq!(body_json(&res), json!({ "path": "docs/" }));
+        let res = serve(app(), "GET", "/assets/css/app.css").await;
+        assert_eq!(body_json(&res), json!({ "rest": "css/app.css" }));
+        assert_eq!(serve(app(), "GET", "/files").await.status_code, 404);
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let mut router = Router::new();
+        router.get("/video.mp4", false, |_req: HttpRequest| async move {
+            let content = vec![7; crate::streaming::CHUNK_SIZE + 1];
+            Ok(HttpResponse::stream(
+                &content,
+                "video.mp4",
+                "http_request_streaming_callback",
+            ))
+        });
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.use_compression(CompressionConfig::new());
+            app
+        };
+        let headers = [("Accept-Encoding", "gzip")];
+        let res = app()
+            .serve(RawHttpRequest::test("GET", "/video.mp4", &headers, &[]))
+            .await;
+
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.body.len(), crate::streaming::CHUNK_SIZE);
+        assert_eq!(res.headers.get("Content-Encoding"), None);
+        match res.streaming_strategy {
+            Some(StreamingStrategy::Callback { ref token, .. }) => {
+                assert_eq!(token.key, "video.mp4");
+                assert_eq!(token.index, 1);
+            }
+            None => panic!("expected a streaming strategy"),
+        }
+        assert!(candid::encode_one(&res).is_ok());
+
+        let res = serve(app(), "HEAD", "/video.mp4").await;
+        assert_eq!(res.status_code, 200);
+        assert!(res.body.is_empty());
+        assert!(res.streaming_strategy.is_none());
+        assert!(!res.headers.contains_key("Content-Length"));
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback() {
+        let mut router = router();
+        router.spa_fallback("/api/", |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::from([("Content-Type".to_string(), "text/html".to_string())]),
+                body: "<div id=\"app\"></div>".to_string().into(),
+            })
+        });
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+        let get =
+            |url: &str, accept: &str| RawHttpRequest::test("GET", url, &[("Accept", accept)], &[]);
+        let html = "text/html,application/xhtml+xml,*/*;q=0.8";
+
+        let res = app().serve(get("/users/42?tab=posts", html)).await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.body, b"<div id=\"app\"></div>".to_vec());
+        assert_eq!(res.upgrade, Some(false));
+        // Registered routes keep working
+        let res = app().serve(get("/user", html)).await;
+        assert_eq!(body_json(&res)["name"], "pluto");
+
+        for url in ["/api", "/api/users/42"] {
+            let res = app().serve(get(url, html)).await;
+            assert_eq!(res.status_code, 404);
+            assert_eq!(body_json(&res)["error"], "Not Found");
+        }
+        let res = app().serve(get("/users/42", "application/json")).await;
+        assert_eq!(res.status_code, 404);
+        let res = app()
+            .serve(RawHttpRequest::test("POST", "/users/42", &[], &[]))
+            .await;
+        assert_eq!(res.status_code, 404);
+        // `/apiary` is not under the API prefix
+        let res = app().serve(get("/apiary", html)).await;
+        assert_eq!(res.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_matched_pattern() {
+        let handler = |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "pattern": req.matched_pattern }).into(),
+            })
+        };
+        let mut users = Router::new();
+        users.get("/:id", false, handler);
+        let mut router = Router::new();
+        router.set_global_prefix("/api".to_string());
+        router.get("/", false, handler).mount("/users", users);
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "GET", "/api/users/42?full=1").await;
+        assert_eq!(body_json(&res), json!({ "pattern": "/api/users/{id}" }));
+        let res = serve(app(), "GET", "/api").await;
+        assert_eq!(body_json(&res), json!({ "pattern": "/api" }));
+        let req: HttpRequest = RawHttpRequest::test("GET", "/api", &[], &[]).into();
+        assert_eq!(req.matched_pattern, None);
+    }
+
+    #[tokio::test]
+    async fn test_url_forms() {
+        for url in [
+            "/user",
+            "/user/?id=1",
+            "https://abc.icp0.io/user",
+            "http://localhost:4943/user?canisterId=abc#top",
+            "//abc.icp0.io/user",
+        ] {
+            let app = HttpServe::new_with_router(router(), "http_request");
+            assert_eq!(serve(app, "GET", url).await.status_code, 200, "{}", url);
+        }
+        assert_eq!(HttpServe::get_path("https://abc.icp0.io"), "");
+        assert_eq!(HttpServe::get_path("//abc.icp0.io/"), "");
+        assert_eq!(HttpServe::get_path("/a/b://c"), "/a/b://c");
+    }
+
+    #[tokio::test]
+    async fn test_powered_by_version() {
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(res.headers.get("X-Powered-By").unwrap(), "Pluto");
+
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.powered_by_version(true);
+        let expected = format!("Pluto/{}", env!("CARGO_PKG_VERSION"));
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(res.headers.get("X-Powered-By").unwrap(), &expected);
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.powered_by_version(true);
+        let res = serve(app, "GET", "/missing").await;
+        assert_eq!(res.headers.get("X-Powered-By").unwrap(), &expected);
+    }
+
+    #[tokio::test]
+    async fn test_pretty_print() {
+        let app = || {
+            let mut app = HttpServe::new_with_router(router(), "http_request");
+            app.use_pretty_print(true);
+            app
+        };
+        let compact = serve(app(), "GET", "/user").await;
+        assert!(!compact.body.contains(&b'\n'));
+
+        let pretty = serve(app(), "GET", "/user?pretty=1").await;
+        assert!(pretty.body.contains(&b'\n'));
+        assert_eq!(
+            serde_json::from_slice::<Value>(&pretty.body).unwrap(),
+            serde_json::from_slice::<Value>(&compact.body).unwrap()
+        );
+        assert_eq!(
+            pretty.headers.get("Content-Type").unwrap(),
+            "application/json"
+        );
+
+        let res = serve(app(), "GET", "/user?pretty=0").await;
+        assert_eq!(res.body, compact.body);
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let res = serve(app, "GET", "/user?pretty=1").await;
+        assert_eq!(res.body, compact.body);
+    }
+
+    #[derive(Clone)]
+    struct Trace(&'static str);
+
+    impl Middleware for Trace {
+        fn before(&self, req: &mut HttpRequest) -> Result<(), HttpResponse> {
+            let trace = req.header("X-Trace").unwrap_or("").to_string();
+            req.headers
+                .retain(|header| !header.0.eq_ignore_ascii_case("X-Trace"));
+            req.headers
+                .push(HeaderField("X-Trace".to_string(), trace + self.0));
+            Ok(())
+        }
+
+        fn after(&self, _req: &HttpRequest, res: &mut HttpResponse) {
+            let trace = res.headers.get("X-Trace").cloned().unwrap_or_default();
+            res.add_raw_header("X-Trace", trace + self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_middleware() {
+        let echo = |req: HttpRequest| async move {
+            let mut res = HttpResponse::no_content();
+            res.add_raw_header(
+                "X-Trace",
+                req.header("X-Trace").unwrap_or("").to_string() + "|",
+            );
+            Ok(res)
+        };
+        let require_token = |req: &mut HttpRequest| match req.header("Authorization") {
+            Some(_) => Ok(()),
+            None => Err(HttpResponse {
+                status_code: 401,
+                headers: HeaderMap::new(),
+                body: json!({ "statusCode": 401, "error": "Unauthorized" }).into(),
+            }),
+        };
+        let mut router = Router::new();
+        router.get("/public", false, echo);
+        router.handle_with(
+            "/admin/stats",
+            false,
+            Method::GET,
+            vec![Box::new(require_token), Box::new(Trace("r"))],
+            echo,
+        );
+        // The stored middleware survives cloning the router
+        let router = router.clone();
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.use_middleware(Trace("g"));
+            app
+        };
+
+        let res = serve(app(), "GET", "/public").await;
+        assert_eq!(res.headers.get("X-Trace").unwrap(), "g|g");
+        let res = serve(app(), "GET", "/admin/stats").await;
+        assert_eq!(res.status_code, 401);
+        assert_eq!(res.headers.get("X-Trace"), None);
+
+        let req =
+            RawHttpRequest::test("GET", "/admin/stats", &[("Authorization", "Bearer t")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(res.status_code, 204);
+        assert_eq!(res.headers.get("X-Trace").unwrap(), "gr|rg");
+    }
+
+    #[tokio::test]
+    async fn test_error_middleware() {
+        let mut router = router();
+        router.get("/fail", false, |_req: HttpRequest| async move {
+            HttpServe::internal_server_error()?;
+            unreachable!()
+        });
+        let decorate = |res: &mut HttpResponse| {
+            res.add_raw_header("X-Support", "https://example.com/support".to_string())
+        };
+
+        let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+        app.use_error_middleware(decorate);
+        let res = serve(app, "GET", "/fail").await;
+        assert_eq!(res.status_code, 500);
+        assert_eq!(
+            res.headers.get("X-Support").unwrap(),
+            "https://example.com/support"
+        );
+
+        let mut app = HttpServe::new_with_router(router, "http_request");
+        app.use_error_middleware(decorate);
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.headers.get("X-Support"), None);
+    }
+
+    #[tokio::test]
+    async fn test_skip_null_fields() {
+        let mut router = Router::new();
+        router.get("/nullable", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "id": 1, "nickname": null, "tags": [null] }).into(),
+            })
+        });
+
+        let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+        app.skip_null_fields(true);
+        let res = serve(app, "GET", "/nullable").await;
+        assert_eq!(body_json(&res), json!({ "id": 1, "tags": [null] }));
+
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "GET", "/nullable").await;
+        assert_eq!(
+            body_json(&res),
+            json!({ "id": 1, "nickname": null, "tags": [null] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_on_post_only_path() {
+        let mut router = Router::new();
+        router.post("/submit", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 201,
+                headers: HeaderMap::new(),
+                body: json!({}).into(),
+            })
+        });
+
+        let app = HttpServe::new_with_router(router.clone(), "http_request");
+        let res = serve(app, "HEAD", "/submit").await;
+        assert_eq!(res.status_code, 405);
+        let allow = res.headers.get("Allow").unwrap();
+        assert!(allow.contains("POST"));
+        assert!(!allow.contains("HEAD"));
+
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "HEAD", "/missing").await;
+        assert_eq!(res.status_code, 404);
+    }
+
+    #[tokio::test]
+    async fn test_auto_head() {
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let get = serve(app, "GET", "/user").await;
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let head = serve(app, "HEAD", "/user").await;
+        assert_eq!(head.status_code, 200);
+        assert!(head.body.is_empty());
+        assert_eq!(
+            head.headers.get("Content-Length").unwrap(),
+            &get.body.len().to_string()
+        );
+        assert_eq!(
+            head.headers.get("Content-Type"),
+            get.headers.get("Content-Type")
+        );
+
+        let mut router = router();
+        router.auto_head(false);
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "HEAD", "/user").await;
+        assert_eq!(res.status_code, 405);
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed() {
+        for method in ["POST", "PUT", "DELETE"] {
+            let app = HttpServe::new_with_router(router(), "http_request");
+            let res = serve(app, method, "/user").await;
+            assert_eq!(res.status_code, 405);
+            assert_eq!(res.headers.get("Allow").unwrap(), "GET, HEAD, OPTIONS");
+            assert_eq!(body_json(&res)["error"], "Method Not Allowed");
+        }
+
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let res = serve(app, "POST", "/missing").await;
+        assert_eq!(res.status_code, 404);
+        assert!(!res.headers.contains_key("Allow"));
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed_with_cors() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().any());
+        let req = RawHttpRequest::test("POST", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app.serve(req).await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(res.headers.get("Allow").unwrap(), "GET, HEAD, OPTIONS");
+        assert_eq!(res.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+
+        // Without a global policy, the 405 gets the policy of the route registered at the path
+        let mut router = router();
+        router.cors(
+            Method::GET,
+            "/user",
+            Cors::new().allow_origins(vec!["https://app.io"]),
+        );
+        let app = HttpServe::new_with_router(router, "http_request");
+        let req = RawHttpRequest::test("POST", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app.serve(req).await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Origin").unwrap(),
+            "https://app.io"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_vary_origin() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().allow_origin("https://app.io").vary_origin(true));
+        let req = RawHttpRequest::test("GET", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app.serve(req).await;
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Origin").unwrap(),
+            "https://app.io"
+        );
+        assert_eq!(res.headers.get("Vary").unwrap(), "Origin");
+
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().allow_origin("https://app.io"));
+        let res = serve(app, "GET", "/user").await;
+        assert!(!res.headers.contains_key("Vary"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_allow_origins() {
+        let app = || {
+            let mut app = HttpServe::new_with_router(router(), "http_request");
+            app.use_cors(Cors::new().allow_origins(vec!["https://app.io", "https://admin.app.io"]));
+            app
+        };
+        let req = RawHttpRequest::test("GET", "/user", &[("Origin", "https://admin.app.io")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Origin").unwrap(),
+            "https://admin.app.io"
+        );
+        assert_eq!(res.headers.get("Vary").unwrap(), "Origin");
+
+        let req = RawHttpRequest::test("POST", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Origin").unwrap(),
+            "https://app.io"
+        );
+
+        let req = RawHttpRequest::test("GET", "/user", &[("Origin", "https://evil.io")], &[]);
+        let res = app().serve(req).await;
+        assert!(!res.headers.contains_key("Access-Control-Allow-Origin"));
+        assert_eq!(res.headers.get("Vary").unwrap(), "Origin");
+    }
+
+    #[tokio::test]
+    async fn test_cors_credentials_with_any_origin() {
+        let app = || {
+            let mut app = HttpServe::new_with_router(router(), "http_request");
+            app.use_cors(Cors::new().any().credentials(true));
+            app
+        };
+        let req = RawHttpRequest::test("GET", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Origin").unwrap(),
+            "https://app.io"
+        );
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Credentials").unwrap(),
+            "true"
+        );
+        assert_eq!(res.headers.get("Vary").unwrap(), "Origin");
+
+        let res = serve(app(), "GET", "/user").await;
+        assert!(!res.headers.contains_key("Access-Control-Allow-Origin"));
+        assert!(!res.headers.contains_key("Access-Control-Allow-Credentials"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let preflight = RawHttpRequest::test(
+            "OPTIONS",
+            "/user",
+            &[
+                ("Origin", "https://app.io"),
+                ("Access-Control-Request-Method", "GET"),
+                ("Access-Control-Request-Headers", "content-type, x-token"),
+            ],
+            &[],
+        );
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().any().max_age(Some(600)));
+        let res = app.serve(preflight.clone()).await;
+        assert_eq!(res.status_code, 204);
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Headers").unwrap(),
+            "content-type, x-token"
+        );
+        assert_eq!(res.headers.get("Access-Control-Max-Age").unwrap(), "600");
+        assert_eq!(
+            res.headers.get("Vary").unwrap(),
+            "Access-Control-Request-Headers"
+        );
+
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().any().allow_headers(vec!["content-type"]));
+        let res = app.serve(preflight).await;
+        assert_eq!(
+            res.headers.get("Access-Control-Allow-Headers").unwrap(),
+            "content-type"
+        );
+        assert!(!res.headers.contains_key("Vary"));
+    }
+
+    #[tokio::test]
+    async fn test_route_cors() {
+        let mut router = router();
+        router.cors(Method::GET, "/user", Cors::new().any());
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.use_cors(Cors::new().allow_origin("https://admin.app.io"));
+            app
+        };
+
+        let req = RawHttpRequest::test("GET", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(res.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+
+        let req = RawHttpRequest::test(
+            "OPTIONS",
+            "/user",
+            &[
+                ("Origin", "https://app.io"),
+                ("Access-Control-Request-Method", "GET"),
+            ],
+            &[],
+        );
+        let res = app().serve(req).await;
+        assert_eq!(res.status_code, 204);
+        assert_eq!(res.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+
+        // The 405 of a wrong method uses the policy of the route at the path too
+        let req = RawHttpRequest::test("POST", "/user", &[("Origin", "https://app.io")], &[]);
+        let res = app().serve(req).await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(res.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+    }
+
+    #[tokio::test]
+    async fn test_not_found_handler() {
+        let mut router = router();
+        router.not_found(|req: HttpRequest| async move {
+            Ok(HttpResponse::builder()
+                .status(404)
+                .text(format!("<h1>{} does not exist</h1>", req.path))
+                .header("Content-Type", "text/html")
+                .build())
+        });
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "GET", "/missing").await;
+        assert_eq!(res.status_code, 404);
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "text/html");
+        assert_eq!(res.body, b"<h1>/missing does not exist</h1>".to_vec());
+
+        let res = serve(app(), "DELETE", "/missing").await;
+        assert_eq!(res.status_code, 404);
+        assert_eq!(res.body, b"<h1>/missing does not exist</h1>".to_vec());
+
+        let res = serve(app(), "POST", "/user").await;
+        assert_eq!(res.status_code, 405);
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic() {
+        let mut router = Router::new();
+        router.get("/panic", false, |_req: HttpRequest| async move {
+            if true {
+                panic!("secret state");
+            }
+            Ok(HttpResponse::no_content())
+        });
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "GET", "/panic").await;
+        assert_eq!(res.status_code, 500);
+        assert_eq!(body_json(&res)["message"], "Internal server error");
+        assert!(!String::from_utf8_lossy(&res.body).contains("secret"));
+
+        let mut app = app();
+        app.on_panic(|message| {
+            HttpResponse::builder()
+                .status(503)
+                .text(format!("caught: {}", message))
+                .build()
+        });
+        let res = serve(app, "GET", "/panic").await;
+        assert_eq!(res.status_code, 503);
+        assert_eq!(res.body, b"caught: secret state".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_trace_method() {
+        let res = serve(
+            HttpServe::new_with_router(router(), "http_request"),
+            "TRACE",
+            "/user",
+        )
+        .await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(res.headers.get("Allow").unwrap(), "GET, HEAD, OPTIONS");
+
+        let mut router = router();
+        router.trace("/user", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "method": req.method }).into(),
+            })
+        });
+        let res = serve(
+            HttpServe::new_with_router(router, "http_request"),
+            "TRACE",
+            "/user",
+        )
+        .await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(body_json(&res), json!({ "method": "TRACE" }));
+    }
+
+    #[tokio::test]
+    async fn test_extension_method() {
+        let mut router = router();
+        router.handle(
+            "/files",
+            false,
+            Method::from_str("PROPFIND").unwrap(),
+            |req: HttpRequest| async move {
+                Ok(HttpResponse {
+                    status_code: 207,
+                    headers: HeaderMap::new(),
+                    body: json!({ "method": req.method }).into(),
+                })
+            },
+        );
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "PROPFIND", "/files").await;
+        assert_eq!(res.status_code, 207);
+        assert_eq!(body_json(&res), json!({ "method": "PROPFIND" }));
+
+        let res = serve(app(), "MKCOL", "/files").await;
+        assert_eq!(res.status_code, 405);
+        assert_eq!(res.headers.get("Allow").unwrap(), "PROPFIND, OPTIONS");
+
+        let res = serve(app(), "GET /", "/files").await;
+        assert_eq!(res.status_code, 400);
+        assert_eq!(body_json(&res)["error"], "invalid HTTP method");
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash() {
+        let app = |policy: TrailingSlash| {
+            let mut router = Router::new();
+            router.trailing_slash(policy);
+            router.get("/foo", false, |_req: HttpRequest| async move {
+                Ok(HttpResponse::json(200, &"no slash"))
+            });
+            if policy == TrailingSlash::Strict {
+                router.get("/foo/", false, |_req: HttpRequest| async move {
+                    Ok(HttpResponse::json(200, &"slash"))
+                });
+            }
+            HttpServe::new_with_router(router, "http_request")
+        };
+
+        let res = serve(app(TrailingSlash::Merge), "GET", "/foo/").await;
+        assert_eq!(body_json(&res), json!("no slash"));
+
+        let res = serve(app(TrailingSlash::Strict), "GET", "/foo/").await;
+        assert_eq!(body_json(&res), json!("slash"));
+        let res = serve(app(TrailingSlash::Strict), "GET", "/foo").await;
+        assert_eq!(body_json(&res), json!("no slash"));
+
+        let res = serve(app(TrailingSlash::RedirectToNoSlash), "GET", "/foo/?page=2").await;
+        assert_eq!(res.status_code, 308);
+        assert_eq!(res.headers.get("Location").unwrap(), "/foo?page=2");
+        let res = serve(app(TrailingSlash::RedirectToNoSlash), "GET", "/foo").await;
+        assert_eq!(body_json(&res), json!("no slash"));
+        let res = serve(app(TrailingSlash::RedirectToNoSlash), "GET", "/").await;
+        assert_eq!(res.status_code, 404);
+
+        for url in [
+            "////evil.com/",
+            "/\\evil.com/",
+            "/\\/evil.com/",
+            "/\t/evil.com/",
+        ] {
+            let res = serve(app(TrailingSlash::RedirectToNoSlash), "GET", url).await;
+            assert_eq!(res.status_code, 308);
+            assert_eq!(res.headers.get("Location").unwrap(), "/evil.com");
+        }
+    }
+
+    #[test]
+    fn test_decode_path() {
+        assert_eq!(decode_path("/users/%20foo").unwrap(), "/users/ foo");
+        assert_eq!(decode_path("/users/caf%C3%A9").unwrap(), "/users/café");
+        assert_eq!(decode_path("/files/a%2Fb%2fc").unwrap(), "/files/a%2Fb%2fc");
+        assert_eq!(decode_path("/discount/100%25").unwrap(), "/discount/100%25");
+        assert_eq!(decode_path("/discount/100%").unwrap(), "/discount/100%25");
+        assert_eq!(decode_path("/users/%FF"), None);
+    }
+
+    #[tokio::test]
+    async fn test_percent_encoded_path() {
+        let mut router = Router::new();
+        router.get("/users/ foo", false, |req: HttpRequest| async move {
+            Ok(HttpResponse::json(200, &req.path))
+        });
+        router.get("/files/{name}", false, |req: HttpRequest| async move {
+            Ok(HttpResponse::json(200, &req.params))
+        });
+        router.get("/docs/{*rest}", false, |req: HttpRequest| async move {
+            Ok(HttpResponse::json(200, &req.params))
+        });
+        let app = || HttpServe::new_with_router(router.clone(), "http_request");
+
+        let res = serve(app(), "GET", "/users/%20foo").await;
+        assert_eq!(body_json(&res), json!("/users/ foo"));
+
+        // An encoded slash stays inside the segment, the params are decoded
+        let res = serve(app(), "GET", "/files/a%2Fb%20c").await;
+        assert_eq!(body_json(&res), json!({ "name": "a/b c" }));
+        let res = serve(app(), "GET", "/files/caf%C3%A9%252F").await;
+        assert_eq!(body_json(&res), json!({ "name": "café%2F" }));
+        let res = serve(app(), "GET", "/docs/my%20notes/a%2Fb/").await;
+        assert_eq!(body_json(&res), json!({ "rest": "my notes/a/b/" }));
+
+        let res = serve(app(), "GET", "/files/%FF").await;
+        assert_eq!(res.status_code, 400);
+        assert_eq!(
+            body_json(&res)["error"],
+            "invalid UTF-8 in the request path"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_with_compression() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_cors(Cors::new().allow_origins(vec!["https://app.io", "https://admin.app.io"]));
+        app.compression(true);
+        let req = RawHttpRequest::test(
+            "GET",
+            "/user",
+            &[("Origin", "https://app.io"), ("Accept-Encoding", "gzip")],
+            &[],
+        );
+        let res = app.serve(req).await;
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        let vary: Vec<&String> = res.headers.get_all("Vary").collect();
+        assert_eq!(vary, vec!["Origin", "Accept-Encoding"]);
+    }
+
+    #[tokio::test]
+    async fn test_etag_with_compression() {
+        let mut router = Router::new();
+        router.get("/app.css", false, |req: HttpRequest| async move {
+            Ok(HttpResponse::css("body {}".to_string()).with_etag(&req))
+        });
+        let app = || {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.compression(true);
+            app
+        };
+        let request =
+            |headers: &[(&str, &str)]| RawHttpRequest::test("GET", "/app.css", headers, &[]);
+
+        let res = app().serve(request(&[])).await;
+        assert_eq!(res.headers.get("ETag").unwrap(), "\"7-a9b342a0ff2b8953\"");
+        let res = app().serve(request(&[("Accept-Encoding", "gzip")])).await;
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        let etag = res.headers.get("ETag").unwrap().clone();
+        assert_eq!(etag, "W/\"7-a9b342a0ff2b8953\"");
+        let res = app()
+            .serve(request(&[
+                ("Accept-Encoding", "gzip"),
+                ("If-None-Match", &etag),
+            ]))
+            .await;
+        assert_eq!(res.status_code, 304);
+    }
+
+    #[tokio::test]
+    async fn test_range_with_compression() {
+        let mut router = Router::new();
+        router.get("/app.js", false, |req: HttpRequest| async move {
+            let content: &'static [u8] = b"console.log('a range of a plain file, 50 bytes');";
+            Ok(crate::static_files::StaticAsset::new(content, "text/javascript").serve(&req))
+        });
+        let mut app = HttpServe::new_with_router(router, "http_request");
+        app.compression(true);
+        let req = RawHttpRequest::test(
+            "GET",
+            "/app.js",
+            &[("Range", "bytes=0-6"), ("Accept-Encoding", "gzip")],
+            &[],
+        );
+        let res = app.serve(req).await;
+        assert_eq!(res.status_code, 206);
+        assert_eq!(res.headers.get("Content-Range").unwrap(), "bytes 0-6/49");
+        assert!(!res.headers.contains_key("Content-Encoding"));
+        assert_eq!(res.body, b"console".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_canister_id() {
+        let mut router = Router::new();
+        router.get("/self", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: req.canister_id().unwrap().to_text().into(),
+            })
+        });
+        let canister_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+
+        let mut app = HttpServe::new_with_router(router, "http_request");
+        app.set_canister_id(canister_id);
+        let res = serve(app, "GET", "/self").await;
+        assert_eq!(res.body, b"ryjl3-tyaaa-aaaaa-aaaba-cai".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_explicit_options_handler_wins() {
+        let mut router = router();
+        router.options("/user", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "handled": "manually" }).into(),
+            })
+        });
+        let mut router = router.global_options(false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 418,
+                headers: HeaderMap::new(),
+                body: json!({}).into(),
+            })
+        });
+        router.options("/only-options", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "handled": "manually" }).into(),
+            })
+        });
+        assert_eq!(router.allowed("/only-options"), vec!["OPTIONS"]);
+
+        let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+        app.use_cors(Cors::new().any());
+        let res = serve(app, "OPTIONS", "/user").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(body_json(&res), json!({ "handled": "manually" }));
+        assert_eq!(res.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+
+        let app = HttpServe::new_with_router(router, "http_request");
+        let res = serve(app, "OPTIONS", "/only-options").await;
+        assert_eq!(res.status_code, 200);
+        assert_eq!(body_json(&res), json!({ "handled": "manually" }));
+    }
+
+    #[tokio::test]
+    async fn test_options_allow_without_auto_options() {
+        let app = |router: &Router| {
+            let mut app = HttpServe::new_with_router(router.clone(), "http_request");
+            app.use_cors(Cors::new().any());
+            app
+        };
+        let mut router = router();
+        router.handle_options(false);
+        let res = serve(app(&router), "OPTIONS", "/user").await;
+        assert_eq!(res.status_code, 404);
+
+        router.options_allow(true);
+        let res = serve(app(&router), "OPTIONS", "/user").await;
+        assert_eq!(res.status_code, 204);
+        assert_eq!(res.headers.get("Allow").unwrap(), "GET, HEAD, OPTIONS");
+        assert!(!res.headers.contains_key("Access-Control-Allow-Origin"));
+        assert!(!res.headers.contains_key("Access-Control-Allow-Methods"));
+
+        let res = serve(app(&router), "OPTIONS", "/unknown").await;
+        assert_eq!(res.status_code, 404);
+    }
+
+    #[tokio::test]
+    async fn test_field_selection() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_field_selection(true);
+        let res = serve(app, "GET", "/user?fields=id,name").await;
+        assert_eq!(body_json(&res), json!({ "id": 1, "name": "pluto" }));
+    }
+
+    #[tokio::test]
+    async fn test_field_selection_without_param() {
+        let mut app = HttpServe::new_with_router(router(), "http_request");
+        app.use_field_selection(true);
+        let res = serve(app, "GET", "/user").await;
+        assert_eq!(
+            body_json(&res),
+            json!({ "id": 1, "name": "pluto", "email": "pluto@example.com" })
+        );
+
+        let app = HttpServe::new_with_router(router(), "http_request");
+        let res = serve(app, "GET", "/user?fields=id").await;
+        assert_eq!(body_json(&res).as_object().unwrap().len(), 3);
+    }
+}
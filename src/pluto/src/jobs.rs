@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use serde_json::Value;
+
+/// A unit of deferred work enqueued by a handler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    pub kind: String,
+    pub payload: Value,
+}
+
+/// The state of an enqueued job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done(Value),
+    Failed(String),
+}
+
+/// The number of finished job statuses kept by default, see `max_finished`.
+pub const MAX_FINISHED: usize = 1000;
+
+struct JobRegistry {
+    next_id: u64,
+    queue: VecDeque<Job>,
+    statuses: HashMap<u64, JobStatus>,
+    // Ids of the done and failed jobs, oldest first
+    finished: VecDeque<u64>,
+    max_finished: usize,
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            queue: VecDeque::new(),
+            statuses: HashMap::new(),
+            finished: VecDeque::new(),
+            max_finished: MAX_FINISHED,
+        }
+    }
+}
+
+impl JobRegistry {
+    fn prune(&mut self) {
+        while self.finished.len() > self.max_finished {
+            if let Some(id) = self.finished.pop_front() {
+                self.statuses.remove(&id);
+            }
+        }
+    }
+}
+
+thread_local! {
+    static JOBS: RefCell<JobRegistry> = RefCell::new(JobRegistry::default());
+}
+
+/// Enqueue a job and return its id.
+///
+/// Handlers enqueue long-running work and answer with `HttpResponse::accepted`,
+/// a timer (e.g. from `ic-cdk-timers`) processes the queue later:
+///
+/// ```ignore
+/// router.post("/reports", false, |req: HttpRequest| async move {
+///     let id = jobs::enqueue("report", serde_json::from_slice(&req.body).unwrap_or_default());
+///     Ok(HttpResponse::accepted(&format!("/jobs/{}", id)))
+/// });
+///
+/// ic_cdk_timers::set_timer_interval(Duration::from_secs(5), || {
+///     while let Some(job) = jobs::next() {
+///         jobs::finish(job.id, build_report(job.payload));
+///     }
+/// });
+/// ```
+pub fn enqueue(kind: &str, payload: Value) -> u64 {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.queue.push_back(Job {
+            id,
+            kind: kind.to_string(),
+            payload,
+        });
+        jobs.statuses.insert(id, JobStatus::Pending);
+        id
+    })
+}
+
+/// Take the oldest pending job from the queue and mark it as running.
+pub fn next() -> Option<Job> {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let job = jobs.queue.pop_front()?;
+        jobs.statuses.insert(job.id, JobStatus::Running);
+        Some(job)
+    })
+}
+
+/// Record the result of a job.
+/// Only the last `max_finished` results are kept, the status of older finished jobs becomes unknown.
+pub fn finish(id: u64, result: Result<Value, String>) {
+    let status = match result {
+        Ok(value) => JobStatus::Done(value),
+        Err(message) => JobStatus::Failed(message),
+    };
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let previous = jobs.statuses.insert(id, status);
+        if !matches!(previous, Some(JobStatus::Done(_) | JobStatus::Failed(_))) {
+            jobs.finished.push_back(id);
+        }
+        jobs.prune();
+    });
+}
+
+/// Set how many finished job statuses are kept, `MAX_FINISHED` by default.
+/// The oldest ones are dropped first, pending and running jobs are never dropped.
+pub fn max_finished(limit: usize) {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        jobs.max_finished = limit;
+        jobs.prune();
+    });
+}
+
+/// Get the state of a job, `None` if the id is unknown or its result was dropped, see `max_finished`.
+pub fn status(id: u64) -> Option<JobStatus> {
+    JOBS.with(|jobs| jobs.borrow().statuses.get(&id).cloned())
+}
+
+/// Get the number of jobs waiting in the queue.
+pub fn pending() -> usize {
+    JOBS.with(|jobs| jobs.borrow().queue.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_queue() {
+        let first = enqueue("report", json!({ "month": 1 }));
+        let second = enqueue("report", json!({ "month": 2 }));
+        assert_eq!(pending(), 2);
+        assert_eq!(status(first), Some(JobStatus::Pending));
+
+        let job = next().unwrap();
+        assert_eq!(job.id, first);
+        assert_eq!(job.payload, json!({ "month": 1 }));
+        assert_eq!(status(first), Some(JobStatus::Running));
+
+        finish(first, Ok(json!("done")));
+        assert_eq!(status(first), Some(JobStatus::Done(json!("done"))));
+        assert_eq!(next().unwrap().id, second);
+        finish(second, Err(String::from("failed")));
+        assert_eq!(
+            status(second),
+            Some(JobStatus::Failed(String::from("failed")))
+        );
+        assert_eq!(next(), None);
+        assert_eq!(status(42), None);
+    }
+
+    #[test]
+    fn test_max_finished() {
+        let ids: Vec<u64> = (0..4).map(|_| enqueue("report", json!(null))).collect();
+        for _ in 0..3 {
+            let job = next().unwrap();
+            finish(job.id, Ok(json!(job.id)));
+        }
+        finish(ids[2], Ok(json!("again")));
+        max_finished(2);
+        assert_eq!(status(ids[0]), None);
+        assert_eq!(status(ids[1]), Some(JobStatus::Done(json!(ids[1]))));
+        assert_eq!(status(ids[2]), Some(JobStatus::Done(json!("again"))));
+        assert_eq!(status(ids[3]), Some(JobStatus::Pending));
+
+        let job = next().unwrap();
+        finish(job.id, Err(String::from("failed")));
+        assert_eq!(status(ids[1]), None);
+        assert_eq!(
+            status(ids[3]),
+            Some(JobStatus::Failed(String::from("failed")))
+        );
+        assert_eq!(JOBS.with(|jobs| jobs.borrow().statuses.len()), 2);
+    }
+}
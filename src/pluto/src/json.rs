@@ -0,0 +1,161 @@
+use std::io::{Result as IoResult, Write};
+
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use serde_json::ser::{CompactFormatter, Formatter};
+use serde_json::Value;
+
+/// Configuration of the JSON serialization used by `HttpResponse::json_with`.
+///
+/// By default the output is the same as `serde_json::to_vec`: fields keep the order
+/// of the serialized type and floats use the shortest representation.
+/// Fixed key ordering and float formatting make the bytes deterministic,
+/// which is needed e.g. for certified responses.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonConfig {
+    sort_keys: bool,
+    float_precision: Option<usize>,
+}
+
+impl JsonConfig {
+    /// Create a config producing the default `serde_json` output.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes the config, set if object keys are sorted at every level and returns changed config
+    pub fn sort_keys(mut self, enabled: bool) -> Self {
+        self.sort_keys = enabled;
+        self
+    }
+
+    /// Consumes the config, set the number of decimals written for floats and returns changed config
+    pub fn float_precision(mut self, decimals: usize) -> Self {
+        self.float_precision = Some(decimals);
+        self
+    }
+
+    /// Serialize `value` to JSON bytes according to the config.
+    pub fn to_vec<T: Serialize + ?Sized>(&self, value: &T) -> serde_json::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        let formatter = ConfiguredFormatter {
+            float_precision: self.float_precision,
+        };
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+        if self.sort_keys {
+            Sorted(&serde_json::to_value(value)?).serialize(&mut serializer)?;
+        } else {
+            value.serialize(&mut serializer)?;
+        }
+        Ok(buffer)
+    }
+}
+
+/// Compact formatter writing floats with a fixed number of decimals when configured.
+struct ConfiguredFormatter {
+    float_precision: Option<usize>,
+}
+
+impl Formatter for ConfiguredFormatter {
+    fn write_f32<W: ?Sized + Write>(&mut self, writer: &mut W, value: f32) -> IoResult<()> {
+        match self.float_precision {
+            Some(decimals) => write!(writer, "{:.*}", decimals, value),
+            None => CompactFormatter.write_f32(writer, value),
+        }
+    }
+
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> IoResult<()> {
+        match self.float_precision {
+            Some(decimals) => write!(writer, "{:.*}", decimals, value),
+            None => CompactFormatter.write_f64(writer, value),
+        }
+    }
+}
+
+/// Serializes a JSON value with the keys of every object in lexicographic order,
+/// independently of the map implementation used by `serde_json`.
+struct Sorted<'a>(&'a Value);
+
+impl Serialize for Sorted<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Object(object) => {
+                let mut entries: Vec<(&String, &Value)> = object.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, &Sorted(value))?;
+                }
+                map.end()
+            }
+            Value::Array(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(&Sorted(value))?;
+                }
+                seq.end()
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Point {
+        y: f64,
+        x: f64,
+        label: &'static str,
+    }
+
+    #[test]
+    fn test_default_output() {
+        let point = Point {
+            y: 2.5,
+            x: 1.0,
+            label: "a",
+        };
+        assert_eq!(
+            JsonConfig::new().to_vec(&point).unwrap(),
+            serde_json::to_vec(&point).unwrap()
+        );
+        assert_eq!(
+            String::from_utf8(JsonConfig::new().to_vec(&point).unwrap()).unwrap(),
+            r#"{"y":2.5,"x":1.0,"label":"a"}"#
+        );
+    }
+
+    #[test]
+    fn test_sorted_keys() {
+        let value = serde_json::json!({ "b": { "z": 1, "a": [{ "d": 1, "c": 2 }] }, "a": null });
+        let config = JsonConfig::new().sort_keys(true);
+        let expected = r#"{"a":null,"b":{"a":[{"c":2,"d":1}],"z":1}}"#;
+        assert_eq!(
+            String::from_utf8(config.to_vec(&value).unwrap()).unwrap(),
+            expected
+        );
+
+        let point = Point {
+            y: 2.5,
+            x: 1.0,
+            label: "a",
+        };
+        assert_eq!(
+            String::from_utf8(config.to_vec(&point).unwrap()).unwrap(),
+            r#"{"label":"a","x":1.0,"y":2.5}"#
+        );
+    }
+
+    #[test]
+    fn test_float_precision() {
+        let config = JsonConfig::new().sort_keys(true).float_precision(2);
+        let value = serde_json::json!({ "price": 10.0 / 3.0, "count": 3 });
+        assert_eq!(
+            String::from_utf8(config.to_vec(&value).unwrap()).unwrap(),
+            r#"{"count":3,"price":3.33}"#
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::http::{HeaderMap, HttpRequest, HttpResponse};
+use crate::middleware::Middleware;
+
+/// The extension key under which the verified claims are stored.
+pub(crate) const CLAIMS: &str = "claims";
+
+/// A verifier of bearer JWTs.
+///
+/// Signature verification is left to the implementation, so canisters pick
+/// the crypto they can afford in Wasm.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::jwt::JwtVerifier;
+/// use serde_json::Value;
+///
+/// struct Hs256 {
+///     secret: Vec<u8>,
+/// }
+///
+/// impl JwtVerifier for Hs256 {
+///     fn verify(&self, token: &str) -> Result<Value, String> {
+///         // Check the signature with `self.secret` and the `exp` claim here
+///         Err(String::from("not implemented"))
+///     }
+/// }
+/// ```
+pub trait JwtVerifier: Send + Sync {
+    /// Verify the signature and the claims of `token` and return the claims,
+    /// or the reason of the rejection.
+    fn verify(&self, token: &str) -> Result<Value, String>;
+}
+
+/// A middleware requiring a bearer JWT accepted by the verifier.
+/// The claims are inserted into the request extensions, see `HttpRequest::claims`.
+#[derive(Clone)]
+pub struct Jwt {
+    verifier: Arc<dyn JwtVerifier>,
+}
+
+impl Jwt {
+    /// Create the middleware for `verifier`.
+    pub fn new(verifier: impl JwtVerifier + 'static) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+        }
+    }
+}
+
+impl Middleware for Jwt {
+    fn before(&self, req: &mut HttpRequest) -> Result<(), HttpResponse> {
+        let token = req
+            .bearer_token()
+            .ok_or_else(|| unauthorized("Missing bearer token"))?;
+        let claims = self
+            .verifier
+            .verify(token)
+            .map_err(|msg| unauthorized(&msg))?;
+        req.extensions.insert(CLAIMS, claims);
+        Ok(())
+    }
+}
+
+fn unauthorized(message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code: 401,
+        headers: HeaderMap::from([("WWW-Authenticate".to_string(), "Bearer".to_string())]),
+        body: json!({
+            "statusCode": 401,
+            "message": message,
+            "error": "Unauthorized"
+        })
+        .into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{HttpServe, RawHttpRequest};
+    use crate::router::Router;
+
+    struct Stub;
+
+    impl JwtVerifier for Stub {
+        fn verify(&self, token: &str) -> Result<Value, String> {
+            match token {
+                "valid" => Ok(json!({ "sub": "alice" })),
+                _ => Err(String::from("Invalid signature")),
+            }
+        }
+    }
+
+    async fn serve(authorization: Option<&str>) -> (u16, Value) {
+        let mut router = Router::new();
+        router.get("/me", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: req.claims().cloned().unwrap_or_default().into(),
+            })
+        });
+        let mut app = HttpServe::new_with_router(router, "http_request");
+        app.use_jwt(Stub);
+        let headers: Vec<(&str, &str)> = authorization
+            .map(|value| vec![("Authorization", value)])
+            .unwrap_or_default();
+        let res = app
+            .serve(RawHttpRequest::test("GET", "/me", &headers, &[]))
+            .await;
+        (res.status_code, serde_json::from_slice(&res.body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_jwt() {
+        assert_eq!(
+            serve(Some("Bearer valid")).await,
+            (200, json!({ "sub": "alice" }))
+        );
+        let (status_code, body) = serve(Some("bearer invalid")).await;
+        assert_eq!(status_code, 401);
+        assert_eq!(body["message"], "Invalid signature");
+        let (status_code, body) = serve(None).await;
+        assert_eq!(status_code, 401);
+        assert_eq!(body["message"], "Missing bearer token");
+        assert_eq!(serve(Some("Basic dXNlcg==")).await.0, 401);
+    }
+}
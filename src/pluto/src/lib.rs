@@ -1,7 +1,26 @@
+pub mod accept;
 pub mod all_or_some;
+pub mod cache_control;
+pub mod certification;
+pub mod compression;
+pub mod cookie;
 pub mod cors;
+pub mod encoding;
+pub mod envelope;
 pub mod http;
+pub mod jobs;
+pub mod json;
+pub mod jwt;
 pub mod method;
+pub mod middleware;
+pub mod multipart;
+pub mod pagination;
+mod panic;
 pub mod router;
+pub mod schema;
 pub mod static_files;
+pub mod streaming;
+pub mod testing;
+#[cfg(feature = "validator")]
+pub mod validation;
 pub mod view;
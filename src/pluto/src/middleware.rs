@@ -0,0 +1,52 @@
+use dyn_clone::{clone_trait_object, DynClone};
+
+use crate::http::{HttpRequest, HttpResponse};
+
+clone_trait_object!(Middleware);
+/// A hook running around route handlers.
+///
+/// Global middleware is registered with `HttpServe::use_middleware`,
+/// route middleware with `Router::handle_with`. Global middleware runs first.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+/// use serde_json::json;
+///
+/// fn require_token(req: &mut HttpRequest) -> Result<(), HttpResponse> {
+///     match req.header("Authorization") {
+///         Some(_) => Ok(()),
+///         None => Err(HttpResponse {
+///             status_code: 401,
+///             headers: HeaderMap::new(),
+///             body: json!({
+///                 "statusCode": 401,
+///                 "message": "Missing Authorization header",
+///                 "error": "Unauthorized"
+///             })
+///             .into(),
+///         }),
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync + DynClone {
+    /// Inspect or modify the request before the handler.
+    /// Returning an error response short-circuits the remaining middleware and the handler.
+    fn before(&self, _req: &mut HttpRequest) -> Result<(), HttpResponse> {
+        Ok(())
+    }
+
+    /// Inspect or modify the response of the handler.
+    /// It runs in the reverse order of `before`, and only when the handler was called.
+    fn after(&self, _req: &HttpRequest, _res: &mut HttpResponse) {}
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut HttpRequest) -> Result<(), HttpResponse> + Send + Sync + DynClone,
+{
+    fn before(&self, req: &mut HttpRequest) -> Result<(), HttpResponse> {
+        self(req)
+    }
+}
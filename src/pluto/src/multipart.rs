@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::http::{HeaderMap, HttpResponse};
+
+/// A single part of a `multipart/form-data` body.
+///
+/// Besides the raw bytes every part keeps its own headers, so file parts
+/// (with a `filename`) can be told apart from plain form fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartPart {
+    /// Headers of the part, keyed by their lowercase name
+    pub headers: HashMap<String, String>,
+    pub data: Vec<u8>,
+}
+
+impl MultipartPart {
+    /// Get the value of the part header matching `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    /// Get a parameter of the `Content-Disposition` header, e.g. `name` or `filename`.
+    pub fn disposition_param(&self, key: &str) -> Option<String> {
+        let disposition = self.header("Content-Disposition")?;
+        split_params(disposition)
+            .into_iter()
+            .skip(1)
+            .find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                if !name.trim().eq_ignore_ascii_case(key) {
+                    return None;
+                }
+                Some(unquote(value.trim()))
+            })
+    }
+
+    /// Get the name of the form field the part belongs to.
+    pub fn name(&self) -> Option<String> {
+        self.disposition_param("name")
+    }
+
+    /// Get the original file name, present only for file parts.
+    pub fn filename(&self) -> Option<String> {
+        self.disposition_param("filename")
+    }
+
+    /// Get the content type of the part.
+    /// Parts without a `Content-Type` header default to `text/plain`.
+    pub fn content_type(&self) -> &str {
+        self.header("Content-Type").unwrap_or("text/plain")
+    }
+
+    /// Check if the part is a file upload rather than a plain form field.
+    pub fn is_file(&self) -> bool {
+        self.filename().is_some()
+    }
+}
+
+/// Get the `boundary` parameter of a `multipart/form-data` content type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = split_params(content_type).into_iter();
+    let media_type = params.next()?;
+    if !media_type
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        let boundary = unquote(value.trim());
+        (!boundary.is_empty()).then_some(boundary)
+    })
+}
+
+/// Split a `multipart/form-data` body into its parts.
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<MultipartPart>, HttpResponse> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut rest = match find(body, &delimiter) {
+        Some(start) => &body[start + delimiter.len()..],
+        None => return Err(malformed("Multipart boundary not found")),
+    };
+
+    let next_delimiter = [b"\r\n".as_slice(), &delimiter].concat();
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = match rest.strip_prefix(b"\r\n") {
+            Some(rest) => rest,
+            None => return Err(malformed("Malformed multipart delimiter")),
+        };
+        let end = match find(rest, &next_delimiter) {
+            Some(end) => end,
+            None => return Err(malformed("Multipart body is not terminated")),
+        };
+        parts.push(parse_part(&rest[..end])?);
+        rest = &rest[end + next_delimiter.len()..];
+    }
+}
+
+fn parse_part(part: &[u8]) -> Result<MultipartPart, HttpResponse> {
+    let (head, data) = match part.strip_prefix(b"\r\n") {
+        // Part without any headers
+        Some(data) => (&b""[..], data),
+        None => match find(part, b"\r\n\r\n") {
+            Some(end) => (&part[..end], &part[end + 4..]),
+            None => return Err(malformed("Malformed multipart part headers")),
+        },
+    };
+    let head = match std::str::from_utf8(head) {
+        Ok(head) => head,
+        Err(_) => return Err(malformed("Multipart part headers are not valid UTF-8")),
+    };
+
+    let mut headers = HashMap::new();
+    for line in head.split("\r\n").filter(|line| !line.is_empty()) {
+        match line.split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+            None => return Err(malformed("Malformed multipart part header")),
+        }
+    }
+    Ok(MultipartPart {
+        headers,
+        data: data.to_vec(),
+    })
+}
+
+/// Split a header value on `;`, leaving quoted strings intact.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+    params
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => inner.replace("\\\"", "\""),
+        None => value.to_string(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+pub(crate) fn malformed(message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code: 400,
+        headers: HeaderMap::new(),
+        body: json!({
+            "statusCode": 400,
+            "message": message,
+        })
+        .into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BODY: &[u8] = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Holiday\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"beach; sunset.png\"\r\n\
+        Content-Type: image/png\r\n\
+        \r\n\
+        \x89PNG\r\n\
+        --XyZ--\r\n";
+
+    #[test]
+    fn test_boundary() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=XyZ"),
+            Some(String::from("XyZ"))
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b\""),
+            Some(String::from("a b"))
+        );
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary("application/json; boundary=XyZ"), None);
+    }
+
+    #[test]
+    fn test_part_headers() {
+        let parts = parse(BODY, "XyZ").unwrap();
+        assert_eq!(parts.len(), 2);
+
+        let field = &parts[0];
+        assert_eq!(field.name(), Some(String::from("title")));
+        assert_eq!(field.filename(), None);
+        assert_eq!(field.content_type(), "text/plain");
+        assert!(!field.is_file());
+        assert_eq!(field.data, b"Holiday");
+
+        let file = &parts[1];
+        assert_eq!(file.name(), Some(String::from("photo")));
+        assert_eq!(file.filename(), Some(String::from("beach; sunset.png")));
+        assert_eq!(file.content_type(), "image/png");
+        assert_eq!(file.header("content-type"), Some("image/png"));
+        assert!(file.is_file());
+        assert_eq!(file.data, b"\x89PNG");
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(parse(BODY, "other").unwrap_err().status_code, 400);
+        let unterminated = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue";
+        assert_eq!(parse(unterminated, "XyZ").unwrap_err().status_code, 400);
+    }
+}
@@ -0,0 +1,129 @@
+use serde::Serialize;
+use serde_json::json;
+
+use crate::http::{HeaderMap, HttpResponse};
+
+/// A page of a list endpoint.
+///
+/// It serializes to `{ "items": [...], "page": 2, "perPage": 10, "total": 42, "totalPages": 5 }`
+/// and `into_response` adds `Link` headers pointing to the neighbouring pages.
+/// Pages are numbered from `1`.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::pagination::Paginated;
+///
+/// let page = Paginated::new(vec![11, 12], 2, 10, 42);
+/// assert_eq!(page.total_pages(), 5);
+/// let res = page.into_response("/items");
+/// assert_eq!(res.headers.get("X-Total-Count").unwrap(), "42");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Paginated<T: Serialize> {
+    items: Vec<T>,
+    page: u64,
+    per_page: u64,
+    total: u64,
+    total_pages: u64,
+}
+
+impl<T: Serialize> Paginated<T> {
+    /// Create a page holding `items` out of `total` elements split into pages of `per_page` elements.
+    pub fn new(items: Vec<T>, page: u64, per_page: u64, total: u64) -> Self {
+        let total_pages = match per_page {
+            0 => 0,
+            _ => total.div_ceil(per_page),
+        };
+        Self {
+            items,
+            page,
+            per_page,
+            total,
+            total_pages,
+        }
+    }
+
+    /// Get the number of pages needed for all elements.
+    pub fn total_pages(&self) -> u64 {
+        self.total_pages
+    }
+
+    /// Build a 200 response with the page as JSON body.
+    /// The `X-Total-Count` header holds the total number of elements and the `Link` header
+    /// lists the `first`, `prev`, `next` and `last` pages of `path` where they exist.
+    pub fn into_response(self, path: &str) -> HttpResponse {
+        let mut res = HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: json!(self).into(),
+        };
+        res.add_raw_header("X-Total-Count", self.total.to_string());
+        if self.total_pages > 0 {
+            res.add_link(&self.page_url(path, 1), "first");
+            if self.page > 1 && self.page <= self.total_pages {
+                res.add_link(&self.page_url(path, self.page - 1), "prev");
+            }
+            if self.page < self.total_pages {
+                res.add_link(&self.page_url(path, self.page + 1), "next");
+            }
+            res.add_link(&self.page_url(path, self.total_pages), "last");
+        }
+        res
+    }
+
+    fn page_url(&self, path: &str, page: u64) -> String {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}page={}&per_page={}",
+            path, separator, page, self.per_page
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::HttpBody;
+
+    #[test]
+    fn test_serialized_shape() {
+        let page = Paginated::new(vec!["a", "b"], 1, 2, 5);
+        assert_eq!(page.total_pages(), 3);
+        assert_eq!(
+            json!(page),
+            json!({
+                "items": ["a", "b"],
+                "page": 1,
+                "perPage": 2,
+                "total": 5,
+                "totalPages": 3,
+            })
+        );
+        assert_eq!(Paginated::new(Vec::<u8>::new(), 1, 10, 0).total_pages(), 0);
+        assert_eq!(Paginated::new(vec![1], 1, 10, 10).total_pages(), 1);
+        assert_eq!(Paginated::new(vec![1], 1, 0, 10).total_pages(), 0);
+    }
+
+    #[test]
+    fn test_links() {
+        let res = Paginated::new(vec![3, 4], 2, 2, 6).into_response("/items?sort=asc");
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.headers.get("X-Total-Count").unwrap(), "6");
+        assert_eq!(
+            res.headers.get("Link").unwrap(),
+            "</items?sort=asc&page=1&per_page=2>; rel=\"first\", \
+             </items?sort=asc&page=1&per_page=2>; rel=\"prev\", \
+             </items?sort=asc&page=3&per_page=2>; rel=\"next\", \
+             </items?sort=asc&page=3&per_page=2>; rel=\"last\""
+        );
+        assert!(matches!(res.body, HttpBody::Value(_)));
+
+        let res = Paginated::new(vec![1], 1, 2, 1).into_response("/items");
+        assert_eq!(
+            res.headers.get("Link").unwrap(),
+            "</items?page=1&per_page=2>; rel=\"first\", </items?page=1&per_page=2>; rel=\"last\""
+        );
+    }
+}
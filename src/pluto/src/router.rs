@@ -1,18 +1,69 @@
-use std::{collections::HashMap, future::Future, pin::Pin};
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
 
 use dyn_clone::{clone_trait_object, DynClone};
 use matchit::{Match, Router as MatchRouter};
 
 use crate::{
-    http::{HttpRequest, HttpResponse},
+    accept::Accept,
+    cors::Cors,
+    http::{HeaderMap, HttpBody, HttpRequest, HttpResponse, RawHttpRequest, ResponseContext},
     method::Method,
+    middleware::Middleware,
 };
 
 /// A container for a handler and a flag indicating whether the handler supports HTTP upgrades.
 #[derive(Clone)]
-pub(crate) struct HandlerContainer {
+pub struct HandlerContainer {
     pub(crate) upgrade: bool,
     pub(crate) handler: Box<dyn Handler>,
+    // The router prefix the handler was registered under
+    pub(crate) prefix: String,
+    // Route middleware, executed after the global one
+    pub(crate) middlewares: Vec<Box<dyn Middleware>>,
+    pub(crate) finalizer: Option<Finalizer>,
+    // Overrides the instruction budget of the HttpServe
+    pub(crate) instruction_budget: Option<u64>,
+    // Overrides the CORS policy of the HttpServe
+    pub(crate) cors: Option<Cors>,
+    // The name of the trailing catch-all param of the route, if any
+    pub(crate) catch_all: Option<String>,
+    // The path the route is registered under, in the matcher syntax
+    pub(crate) pattern: Option<String>,
+}
+
+/// A hook computing late headers from the final response of a route, see `Router::finalize`.
+pub type Finalizer = Arc<dyn Fn(&mut ResponseContext) + Send + Sync>;
+
+impl HandlerContainer {
+    /// Check if the handler supports HTTP upgrades.
+    pub fn upgrade(&self) -> bool {
+        self.upgrade
+    }
+
+    /// Get the registered handler.
+    pub fn handler(&self) -> &dyn Handler {
+        self.handler.as_ref()
+    }
+}
+
+/// A registered route, kept in registration order.
+#[derive(Clone)]
+struct Route {
+    method: Method,
+    path: String,
+    container: HandlerContainer,
+}
+
+/// How the router treats a trailing slash, see `Router::trailing_slash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/foo/` and `/foo` are different paths
+    Strict,
+    /// `/foo/` and `/foo` are the same path
+    #[default]
+    Merge,
+    /// Requests for `/foo/` are redirected to `/foo` with a 308
+    RedirectToNoSlash,
 }
 
 /// A router for HTTP requests.
@@ -20,9 +71,20 @@ pub(crate) struct HandlerContainer {
 #[derive(Clone)]
 pub struct Router {
     prefix: String,
-    trees: HashMap<Method, MatchRouter<HandlerContainer>>,
+    routes: Vec<Route>,
+    // The trees store indexes into `routes`
+    trees: HashMap<Method, MatchRouter<usize>>,
     pub(crate) handle_options: bool,
+    pub(crate) options_allow: bool,
+    pub(crate) auto_head: bool,
+    pub(crate) trailing_slash: TrailingSlash,
+    default_content_type: Option<String>,
+    #[cfg(feature = "dev")]
+    dev_routes_path: Option<String>,
     pub(crate) global_options: Option<HandlerContainer>,
+    spa_fallback: Option<HandlerContainer>,
+    spa_api_prefix: String,
+    pub(crate) not_found: Option<HandlerContainer>,
 }
 
 impl Router {
@@ -39,13 +101,24 @@ impl Router {
     pub fn new() -> Self {
         Self {
             prefix: String::from(""),
+            routes: Vec::new(),
             trees: HashMap::new(),
             handle_options: true,
+            options_allow: false,
+            auto_head: true,
+            trailing_slash: TrailingSlash::Merge,
+            default_content_type: None,
+            #[cfg(feature = "dev")]
+            dev_routes_path: None,
             global_options: None,
+            spa_fallback: None,
+            spa_api_prefix: String::new(),
+            not_found: None,
         }
     }
 
     /// Set a prefix for all paths registered on the router.
+    /// Trailing slashes of the prefix are dropped, so `/api/` and `/api` are the same prefix.
     /// # Examples
     ///
     /// ``` rust
@@ -55,26 +128,39 @@ impl Router {
     /// router.set_global_prefix("/api".to_string());
     /// ```
     pub fn set_global_prefix(&mut self, p: String) -> &mut Self {
-        self.prefix = p;
+        self.prefix = p.trim_end_matches('/').to_string();
         self
     }
 
+    /// Get the global prefix of the router, without a trailing slash.
+    pub fn global_prefix(&self) -> &str {
+        &self.prefix
+    }
+
     /// Register a handler for a path and method.
     /// The handler is called for requests with a matching path and method.
+    ///
+    /// Path params are written as `{id}` and catch-all params as `{*rest}`.
+    /// The `:id` and `*rest` segments known from other frameworks are accepted too
+    /// and translated when the route is registered.
+    /// A catch-all captures the non-empty remainder of the path, e.g. `docs/2024/report.pdf`
+    /// for `/files/docs/2024/report.pdf` and `/files/{*path}`, including a trailing slash.
+    ///
+    /// Extension methods, like the WebDAV `PROPFIND`, are registered with the method parsed from its name.
     /// # Examples
     ///
     /// ``` rust
+    /// use std::str::FromStr;
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.handle("/hello", false, Method::GET, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from GET",
@@ -82,6 +168,10 @@ impl Router {
     ///         .into(),
     ///     })
     /// });
+    /// let propfind = Method::from_str("PROPFIND").unwrap();
+    /// router.handle("/files", false, propfind, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse::no_content())
+    /// });
     /// ```
     pub fn handle(
         &mut self,
@@ -90,23 +180,339 @@ impl Router {
         method: Method,
         handler: impl Handler + 'static,
     ) -> &mut Self {
+        self.handle_with(path, upgrade, method, Vec::new(), handler)
+    }
+
+    /// Register a handler for a path and method with a middleware stack of its own.
+    /// The route middleware runs after the global middleware registered with `HttpServe::use_middleware`.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// let log = |req: &mut HttpRequest| {
+    ///     println!("{} {}", req.method, req.path);
+    ///     Ok(())
+    /// };
+    /// router.handle_with("/admin/stats", false, Method::GET, vec![Box::new(log)], |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({}).into(),
+    ///     })
+    /// });
+    /// ```
+    pub fn handle_with(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        method: Method,
+        middlewares: Vec<Box<dyn Middleware>>,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        let global_path = self.global_path(path);
+        match self
+            .trees
+            .entry(method.clone())
+            .or_default()
+            .insert(global_path.clone(), self.routes.len())
+        {
+            Err(err) => panic!("\nERROR: {}\n", err),
+            Ok(_) => {}
+        }
+        let catch_all = catch_all_name(&global_path);
+        self.routes.push(Route {
+            method,
+            path: global_path.clone(),
+            container: HandlerContainer {
+                handler: Box::new(handler),
+                upgrade: upgrade,
+                prefix: self.prefix.clone(),
+                middlewares,
+                finalizer: None,
+                instruction_budget: None,
+                cors: None,
+                catch_all,
+                pattern: route_pattern(&global_path),
+            },
+        });
+        self
+    }
+
+    /// Register all routes of `other` under `prefix`, e.g. to compose routers defined in separate modules.
+    /// Route middleware, finalizers and budgets are kept, the router-level settings of `other`
+    /// (like `handle_options`, `global_options` or `not_found`) are not.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    ///
+    /// fn users_router() -> Router {
+    ///     let mut router = Router::new();
+    ///     router.get("/:id", false, |_req: HttpRequest| async move {
+    ///         Ok(HttpResponse::no_content())
+    ///     });
+    ///     router
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.mount("/users", users_router());
+    /// assert_eq!(router.allowed("/users/1"), vec!["GET"]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a route of `other` collides with a route already registered.
+    pub fn mount(&mut self, prefix: &str, other: Router) -> &mut Self {
+        let prefix = prefix.trim_end_matches('/');
+        for route in other.routes {
+            // The root route of `other` is registered under the empty path
+            let path = match route.path.as_str() {
+                "" => "/",
+                path => path,
+            };
+            let global_path = self.global_path(&format!("{}{}", prefix, path));
+            let inserted = self
+                .trees
+                .entry(route.method.clone())
+                .or_default()
+                .insert(global_path.clone(), self.routes.len());
+            if let Err(err) = inserted {
+                panic!(
+                    "cannot mount {} '{}' under '{}': {}",
+                    route.method, global_path, prefix, err
+                );
+            }
+            let mut container = route.container;
+            container.prefix = self.prefix.clone() + prefix + &container.prefix;
+            container.pattern = route_pattern(&global_path);
+            self.routes.push(Route {
+                method: route.method,
+                path: global_path,
+                container,
+            });
+        }
+        self
+    }
+
+    /// Register a handler for a path and method, overwriting the handler already registered
+    /// for the same method and path instead of panicking, e.g. when reconfiguring in `post_upgrade`.
+    /// The overwritten route's middleware, finalizer, budget and CORS policy are dropped.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/status", false, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::no_content())
+    /// });
+    /// router.replace("/status", false, Method::GET, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::service_unavailable(Some(300)))
+    /// });
+    /// ```
+    pub fn replace(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        method: Method,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        let global_path = self.global_path(path);
+        let route = self
+            .routes
+            .iter_mut()
+            .find(|route| route.method == method && route.path == global_path);
+        match route {
+            Some(route) => {
+                route.container = HandlerContainer {
+                    handler: Box::new(handler),
+                    upgrade,
+                    prefix: self.prefix.clone(),
+                    middlewares: Vec::new(),
+                    finalizer: None,
+                    instruction_budget: None,
+                    cors: None,
+                    catch_all: catch_all_name(&global_path),
+                    pattern: route_pattern(&global_path),
+                };
+                self
+            }
+            None => self.handle(path, upgrade, method, handler),
+        }
+    }
+
+    /// Remove the route registered for `method` and `path`.
+    /// Returns whether a route was removed.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/debug", false, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::no_content())
+    /// });
+    /// assert!(router.remove(Method::GET, "/debug"));
+    /// assert!(!router.remove(Method::GET, "/debug"));
+    /// ```
+    pub fn remove(&mut self, method: Method, path: &str) -> bool {
+        let global_path = self.global_path(path);
+        let index = self
+            .routes
+            .iter()
+            .position(|route| route.method == method && route.path == global_path);
+        match index {
+            Some(index) => {
+                self.routes.remove(index);
+                self.rebuild_trees();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuild the trees from `routes`, since the matcher does not support removal
+    /// and the indexes of the routes after a removed one are shifted.
+    fn rebuild_trees(&mut self) {
+        self.trees = HashMap::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            self.trees
+                .entry(route.method.clone())
+                .or_default()
+                .insert(route.path.clone(), index)
+                .expect("routes registered before do not conflict");
+        }
+    }
+
+    /// Attach a finalizer to the route registered for `method` and `path`.
+    /// It runs on the final response, after all response plugins, and may add headers
+    /// computed from the status code and the body bytes, e.g. a signature over the body.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse, ResponseContext};
+    /// use pluto::method::Method;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/report", false, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::css("body {}".to_string()))
+    /// });
+    /// router.finalize(Method::GET, "/report", |res: &mut ResponseContext| {
+    ///     let length = res.body.len().to_string();
+    ///     res.set_header("X-Body-Length", length);
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route is registered for `method` and `path`.
+    pub fn finalize(
+        &mut self,
+        method: Method,
+        path: &str,
+        finalizer: impl Fn(&mut ResponseContext) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.container_mut(&method, path).finalizer = Some(Arc::new(finalizer));
+        self
+    }
+
+    /// Set the instruction budget of the route registered for `method` and `path`,
+    /// overriding `HttpServe::instruction_budget`, e.g. for a known-slow endpoint.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/report", false, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::no_content())
+    /// });
+    /// router.instruction_budget(Method::GET, "/report", 5_000_000_000);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route is registered for `method` and `path`.
+    pub fn instruction_budget(&mut self, method: Method, path: &str, budget: u64) -> &mut Self {
+        self.container_mut(&method, path).instruction_budget = Some(budget);
+        self
+    }
+
+    /// Set the CORS policy of the route registered for `method` and `path`,
+    /// overriding `HttpServe::use_cors`, e.g. to open a public endpoint to any origin.
+    /// Preflight requests get the policy of the route named by their `Access-Control-Request-Method`.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::cors::Cors;
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/public/stats", false, |_req: HttpRequest| async move {
+    ///     Ok(HttpResponse::no_content())
+    /// });
+    /// router.cors(Method::GET, "/public/stats", Cors::new().any());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route is registered for `method` and `path`.
+    pub fn cors(&mut self, method: Method, path: &str, cors: Cors) -> &mut Self {
+        self.container_mut(&method, path).cors = Some(cors);
+        self
+    }
+
+    fn container_mut(&mut self, method: &Method, path: &str) -> &mut HandlerContainer {
+        let global_path = self.global_path(path);
+        let route = self
+            .routes
+            .iter_mut()
+            .find(|route| route.method == *method && route.path == global_path);
+        match route {
+            Some(route) => &mut route.container,
+            None => panic!("no route registered for {} '{}'", method, path),
+        }
+    }
+
+    /// Get the path a route is registered under, with the router prefix and the matcher syntax.
+    fn global_path(&self, path: &str) -> String {
         if !path.starts_with('/') {
             panic!("expect path beginning with '/', found: '{}'", path);
         }
-        let mut global_path = self.prefix.to_owned() + path;
-        if global_path.ends_with("/") {
+        let mut global_path = translate_path(&(self.prefix.to_owned() + path));
+        if global_path.ends_with("/") && self.trailing_slash != TrailingSlash::Strict {
             global_path.pop();
         }
+        global_path
+    }
 
-        match self.trees.entry(method).or_default().insert(
-            global_path,
-            HandlerContainer {
-                handler: Box::new(handler),
-                upgrade: upgrade,
-            },
-        ) {
-            Err(err) => panic!("\nERROR: {}\n", err),
-            Ok(_) => {}
+    /// Register a handler for a path and method only when `enabled` is true.
+    /// It is useful for routes that should exist only in some deployments, e.g. debug or experimental endpoints.
+    pub fn handle_if(
+        &mut self,
+        enabled: bool,
+        path: &str,
+        upgrade: bool,
+        method: Method,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        if enabled {
+            self.handle(path, upgrade, method, handler);
         }
         self
     }
@@ -120,7 +526,10 @@ impl Router {
     ) -> Result<Match<&HandlerContainer>, String> {
         if let Some(tree_at_path) = self.trees.get(&method) {
             if let Ok(match_result) = tree_at_path.at(path) {
-                return Ok(match_result);
+                return Ok(Match {
+                    value: &self.routes[*match_result.value].container,
+                    params: match_result.params,
+                });
             }
         }
 
@@ -136,16 +545,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.get("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from GET",
@@ -158,22 +566,94 @@ impl Router {
         self.handle(path, upgrade, Method::GET, handler)
     }
 
+    /// Register a handler for GET requests at a path only when `enabled` is true.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let debug_enabled = false;
+    /// let mut router = Router::new();
+    /// router.get_if(debug_enabled, "/debug", false, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({
+    ///             "statusCode": 200,
+    ///             "message": "Debug information",
+    ///         })
+    ///         .into(),
+    ///     })
+    /// });
+    /// assert!(router.allowed("/debug").is_empty());
+    /// ```
+    pub fn get_if(
+        &mut self,
+        enabled: bool,
+        path: &str,
+        upgrade: bool,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        self.handle_if(enabled, path, upgrade, Method::GET, handler)
+    }
+
+    /// Register a handler for GET requests at a path and post-process its successful responses with `after`.
+    /// `after` is skipped when the handler returns an error or a response with an error status code.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.get_then(
+    ///     "/hello",
+    ///     false,
+    ///     |req: HttpRequest| async move {
+    ///         Ok(HttpResponse {
+    ///             status_code: 200,
+    ///             headers: HeaderMap::new(),
+    ///             body: json!({
+    ///                 "statusCode": 200,
+    ///                 "message": "Hello World from GET",
+    ///             })
+    ///             .into(),
+    ///         })
+    ///     },
+    ///     |mut res: HttpResponse| {
+    ///         res.add_raw_header("Cache-Control", "no-store".to_string());
+    ///         res
+    ///     },
+    /// );
+    /// ```
+    pub fn get_then(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        handler: impl Handler + 'static,
+        after: fn(HttpResponse) -> HttpResponse,
+    ) -> &mut Self {
+        self.handle(path, upgrade, Method::GET, Then::new(handler, after))
+    }
+
     /// Register a handler for HEAD requests at a path.
     /// The handler is called for requests with the HEAD method and a matching path.
     /// # Examples
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.head("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from HEAD",
@@ -197,16 +677,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.options("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from OPTIONS",
@@ -230,16 +709,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.post("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from POST",
@@ -263,16 +741,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.put("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from PUT",
@@ -291,16 +768,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.patch("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from PATCH",
@@ -324,16 +800,15 @@ impl Router {
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
     /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
     /// router.delete("/hello", false, |req: HttpRequest| async move {
     ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from DELETE",
@@ -351,66 +826,395 @@ impl Router {
         self.handle(path, upgrade, Method::DELETE, handler)
     }
 
-    /// Allow the router to handle OPTIONS requests.
-    /// If enabled, the router will automatically respond to OPTIONS requests with the allowed methods for a path.
-    /// If disabled, the router will respond to OPTIONS requests with a 404.
-    /// # Examples
-    ///
-    /// ``` rust
-    /// use pluto::router::Router;
-    ///
-    /// let mut router = Router::new();
-    /// router.handle_options(true);
-    /// ```
-    pub fn handle_options(&mut self, handle: bool) {
-        self.handle_options = handle;
-    }
-
-    /// Register a default handler for not registered requests.
-    /// The handler is called for requests when router can't matching path or method to any handler.
+    /// Register a handler for TRACE requests at a path.
+    /// The handler is called for requests with the TRACE method and a matching path.
     /// # Examples
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
-    /// router.global_options(false, |req: HttpRequest| async move {
-    ///    Ok(HttpResponse {
-    ///         status_code: 404,
-    ///         headers: HashMap::new(),
+    /// router.trace("/hello", false, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
-    ///             "statusCode": 404,
-    ///             "message": "Not Found",
+    ///             "statusCode": 200,
+    ///             "message": "Hello World from TRACE",
     ///         })
     ///         .into(),
     ///     })
     /// });
     /// ```
-    pub fn global_options(mut self, upgrade: bool, handler: impl Handler + 'static) -> Self {
-        self.global_options = Some(HandlerContainer {
-            handler: Box::new(handler),
-            upgrade: upgrade,
-        });
-        self
+    pub fn trace(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        self.handle(path, upgrade, Method::TRACE, handler)
     }
 
-    /// Get the allowed methods for a path.
+    /// Register a handler for CONNECT requests at a path.
+    /// The handler is called for requests with the CONNECT method and a matching path.
     /// # Examples
     ///
     /// ``` rust
     /// use pluto::router::Router;
-    /// use pluto::http::{HttpRequest, HttpResponse};
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
     /// use serde_json::json;
-    /// use std::collections::HashMap;
     ///
     /// let mut router = Router::new();
-    /// router.get("/hello", false, |req: HttpRequest| async move {
-    ///    Ok(HttpResponse {
+    /// router.connect("/hello", false, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({
+    ///             "statusCode": 200,
+    ///             "message": "Hello World from CONNECT",
+    ///         })
+    ///         .into(),
+    ///     })
+    /// });
+    /// ```
+    pub fn connect(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        handler: impl Handler + 'static,
+    ) -> &mut Self {
+        self.handle(path, upgrade, Method::CONNECT, handler)
+    }
+
+    /// Register one handler for several methods at a path,
+    /// e.g. for an endpoint treating GET and POST requests the same way.
+    /// The handler can tell the methods apart with `HttpRequest::method_enum`.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use pluto::method::Method;
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.on("/search", false, &[Method::GET, Method::POST], |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({ "method": req.method }).into(),
+    ///     })
+    /// });
+    /// ```
+    pub fn on(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        methods: &[Method],
+        handler: impl Handler + Clone + 'static,
+    ) -> &mut Self {
+        for method in methods {
+            self.handle(path, upgrade, method.clone(), handler.clone());
+        }
+        self
+    }
+
+    /// Register one handler for GET, POST, PUT, PATCH and DELETE requests at a path,
+    /// e.g. for proxies or method-agnostic endpoints.
+    /// HEAD is left to `auto_head`, which runs the GET handler and strips the body,
+    /// and OPTIONS is left out, so preflight requests are still answered by the router.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.all("/proxy", false, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({
+    ///             "statusCode": 200,
+    ///             "method": req.method,
+    ///         })
+    ///         .into(),
+    ///     })
+    /// });
+    /// ```
+    pub fn all(
+        &mut self,
+        path: &str,
+        upgrade: bool,
+        handler: impl Handler + Clone + 'static,
+    ) -> &mut Self {
+        self.on(
+            path,
+            upgrade,
+            &[
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            handler,
+        )
+    }
+
+    /// Register a GET handler at `/candid.did` returning the candid interface description of the canister.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.candid("service : { http_request : (HttpRequest) -> (HttpResponse) query }");
+    /// ```
+    pub fn candid(&mut self, did: &str) -> &mut Self {
+        let did = did.to_string();
+        self.get("/candid.did", false, move |_req: HttpRequest| {
+            let did = did.clone();
+            async move {
+                Ok(HttpResponse {
+                    status_code: 200,
+                    headers: HeaderMap::from([(
+                        "Content-Type".to_string(),
+                        "text/plain; charset=utf-8".to_string(),
+                    )]),
+                    body: did.into(),
+                })
+            }
+        })
+    }
+
+    /// Allow the router to handle OPTIONS requests.
+    /// If enabled, the router will automatically respond to OPTIONS requests with the allowed methods for a path.
+    /// If disabled, the router will respond to OPTIONS requests with a 404.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.handle_options(true);
+    /// ```
+    pub fn handle_options(&mut self, handle: bool) {
+        self.handle_options = handle;
+    }
+
+    /// Answer OPTIONS requests to known paths with only an `Allow` header when `handle_options` is disabled.
+    /// The response is a plain 204 without CORS headers, unknown paths still respond with a 404.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.handle_options(false);
+    /// router.options_allow(true);
+    /// ```
+    pub fn options_allow(&mut self, enabled: bool) {
+        self.options_allow = enabled;
+    }
+
+    /// Answer HEAD requests to paths without a HEAD handler with the GET handler of the path.
+    /// The body is discarded while the status code and headers are kept, and `Content-Length`
+    /// is set to the length of the GET body. Enabled by default.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.auto_head(false);
+    /// ```
+    pub fn auto_head(&mut self, enabled: bool) {
+        self.auto_head = enabled;
+    }
+
+    /// Set how trailing slashes are treated, `TrailingSlash::Merge` by default.
+    /// With `TrailingSlash::Strict` the slash is part of the registered path, so `/foo/`
+    /// and `/foo` can have different handlers, and with `TrailingSlash::RedirectToNoSlash`
+    /// requests for `/foo/?page=2` get a 308 redirect to `/foo?page=2`.
+    ///
+    /// The policy applies to the routes registered after it is set, so set it first.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::{Router, TrailingSlash};
+    ///
+    /// let mut router = Router::new();
+    /// router.trailing_slash(TrailingSlash::RedirectToNoSlash);
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Set the content type of responses from the router handlers that do not set one,
+    /// e.g. `text/html` for routers serving pages.
+    /// JSON bodies (`HttpBody::Value`) keep the `application/json` default.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.default_content_type("text/html; charset=utf-8");
+    /// ```
+    pub fn default_content_type(&mut self, mime: &str) {
+        self.default_content_type = Some(mime.to_string());
+    }
+
+    /// Label the response with the default content type if it has none.
+    pub(crate) fn apply_default_content_type(&self, res: &mut HttpResponse) {
+        let mime = match self.default_content_type {
+            Some(ref mime) => mime,
+            None => return,
+        };
+        if matches!(res.body, HttpBody::Value(_)) || res.headers.contains_key("Content-Type") {
+            return;
+        }
+        res.add_raw_header("Content-Type", mime.clone());
+    }
+
+    /// Register a handler answering OPTIONS requests for registered paths without their own OPTIONS handler,
+    /// in place of the automatic OPTIONS response. It is only used while `handle_options` is enabled,
+    /// requests for unknown paths are answered by `not_found`.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.global_options(false, |req: HttpRequest| async move {
+    ///    Ok(HttpResponse {
+    ///         status_code: 404,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({
+    ///             "statusCode": 404,
+    ///             "message": "Not Found",
+    ///         })
+    ///         .into(),
+    ///     })
+    /// });
+    /// ```
+    pub fn global_options(mut self, upgrade: bool, handler: impl Handler + 'static) -> Self {
+        self.global_options = Some(HandlerContainer {
+            handler: Box::new(handler),
+            upgrade: upgrade,
+            prefix: String::new(),
+            middlewares: Vec::new(),
+            finalizer: None,
+            instruction_budget: None,
+            cors: None,
+            catch_all: None,
+            pattern: None,
+        });
+        self
+    }
+
+    /// Answer GET requests that match no route and accept HTML with `handler`, usually rendering
+    /// the entry document of a single-page app, so client-side routes like `/users/42` work on reload.
+    /// Requests for `api_prefix` and the paths below it keep getting a JSON 404,
+    /// an empty `api_prefix` sends every unmatched HTML request to the app.
+    /// # Examples
+    ///
+    /// ```ignore
+    /// router.spa_fallback("/api", |_req: HttpRequest| async move {
+    ///     render_view!(crate::compiled::templates::index_html);
+    /// });
+    /// ```
+    pub fn spa_fallback(&mut self, api_prefix: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.spa_api_prefix = api_prefix.trim_end_matches('/').to_string();
+        self.spa_fallback = Some(HandlerContainer {
+            handler: Box::new(handler),
+            upgrade: false,
+            prefix: String::new(),
+            middlewares: Vec::new(),
+            finalizer: None,
+            instruction_budget: None,
+            cors: None,
+            catch_all: None,
+            pattern: None,
+        });
+        self
+    }
+
+    /// Register a handler for requests matching no route, replacing the default JSON 404,
+    /// e.g. to render a branded HTML page. It runs like a route handler, with the response plugins.
+    /// Known paths requested with another method still get a 405, OPTIONS requests for known paths
+    /// are answered as configured with `handle_options` and `global_options`,
+    /// and `spa_fallback` takes precedence for the requests it applies to.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HttpRequest, HttpResponse};
+    ///
+    /// let mut router = Router::new();
+    /// router.not_found(|req: HttpRequest| async move {
+    ///     Ok(HttpResponse::builder()
+    ///         .status(404)
+    ///         .text(format!("<h1>{} does not exist</h1>", req.path))
+    ///         .header("Content-Type", "text/html")
+    ///         .build())
+    /// });
+    /// ```
+    pub fn not_found(&mut self, handler: impl Handler + 'static) -> &mut Self {
+        self.not_found = Some(HandlerContainer {
+            handler: Box::new(handler),
+            upgrade: false,
+            prefix: String::new(),
+            middlewares: Vec::new(),
+            finalizer: None,
+            instruction_budget: None,
+            cors: None,
+            catch_all: None,
+            pattern: None,
+        });
+        self
+    }
+
+    /// Get the single-page app fallback for an unmatched GET request, if it applies.
+    pub(crate) fn lookup_spa_fallback(
+        &self,
+        path: &str,
+        req: &RawHttpRequest,
+    ) -> Option<&HandlerContainer> {
+        let fallback = self.spa_fallback.as_ref()?;
+        let prefix = self.spa_api_prefix.as_str();
+        let is_api = !prefix.is_empty()
+            && path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        let accept = req
+            .header("Accept")
+            .map_or_else(Accept::default, Accept::parse);
+        (!is_api && accept.accepts("text/html")).then_some(fallback)
+    }
+
+    /// Get the allowed methods for a path.
+    /// HEAD is included for paths with a GET handler while `auto_head` is enabled.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/hello", false, |req: HttpRequest| async move {
+    ///    Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from GET",
@@ -421,7 +1225,7 @@ impl Router {
     /// router.post("/hello", false, |req: HttpRequest| async move {
     ///   Ok(HttpResponse {
     ///         status_code: 200,
-    ///         headers: HashMap::new(),
+    ///         headers: HeaderMap::new(),
     ///         body: json!({
     ///             "statusCode": 200,
     ///             "message": "Hello World from POST",
@@ -431,7 +1235,7 @@ impl Router {
     /// });
     /// let mut allowed = router.allowed("/hello");
     /// allowed.sort();
-    /// assert_eq!(allowed, vec!["GET", "OPTIONS", "POST"]);
+    /// assert_eq!(allowed, vec!["GET", "HEAD", "OPTIONS", "POST"]);
     pub fn allowed(&self, path: &str) -> Vec<&str> {
         let mut allowed = match path {
             "*" => {
@@ -459,12 +1263,218 @@ impl Router {
                 .collect::<Vec<_>>(),
         };
 
-        if !allowed.is_empty() {
+        // HEAD is answered by the GET handler, see `auto_head`
+        if self.auto_head
+            && allowed.contains(&Method::GET.as_str())
+            && !allowed.contains(&Method::HEAD.as_str())
+        {
+            allowed.push(Method::HEAD.as_ref());
+        }
+
+        // An explicit OPTIONS handler makes the path known even without other methods
+        let explicit_options = self
+            .trees
+            .get(&Method::OPTIONS)
+            .map(|node| node.at(path).is_ok())
+            .unwrap_or(false);
+        if !allowed.is_empty() || explicit_options {
             allowed.push(Method::OPTIONS.as_ref())
         }
 
         allowed
     }
+
+    /// Call `f` for every registered route in registration order, e.g. for startup checks or warm-up.
+    /// Paths are reported with the router prefix applied.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    /// use pluto::http::{HeaderMap, HttpRequest, HttpResponse};
+    /// use serde_json::json;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/hello", false, |req: HttpRequest| async move {
+    ///     Ok(HttpResponse {
+    ///         status_code: 200,
+    ///         headers: HeaderMap::new(),
+    ///         body: json!({}).into(),
+    ///     })
+    /// });
+    /// let mut count = 0;
+    /// router.for_each_route(|method, path, _container| {
+    ///     println!("{} {}", method, path);
+    ///     count += 1;
+    /// });
+    /// assert_eq!(count, 1);
+    /// ```
+    pub fn for_each_route(&self, mut f: impl FnMut(&Method, &str, &HandlerContainer)) {
+        for route in self.routes.iter() {
+            f(&route.method, &route.path, &route.container);
+        }
+    }
+
+    /// Get the method and path of every registered route in registration order.
+    /// Paths are reported with the router prefix applied.
+    pub fn routes(&self) -> Vec<(&Method, &str)> {
+        self.routes
+            .iter()
+            .map(|route| (&route.method, route.path.as_str()))
+            .collect()
+    }
+
+    /// Register an endpoint at `path` answering every method with the parsed request as JSON:
+    /// method, path, params, query, headers and a preview of the body (up to 1 KiB).
+    /// It is available only with the `dev` feature, so request data never leaks in production.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.echo("/debug/echo");
+    /// ```
+    #[cfg(feature = "dev")]
+    pub fn echo(&mut self, path: &str) -> &mut Self {
+        const PREVIEW_LEN: usize = 1024;
+        self.all(path, false, |req: HttpRequest| async move {
+            let headers: Vec<(&str, &str)> = req
+                .headers
+                .iter()
+                .map(|header| (header.0.as_str(), header.1.as_str()))
+                .collect();
+            let preview = &req.body[..req.body.len().min(PREVIEW_LEN)];
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: serde_json::json!({
+                    "method": req.method,
+                    "url": req.url,
+                    "path": req.path,
+                    "params": req.params,
+                    "query": req.query_value(),
+                    "headers": headers,
+                    "body": String::from_utf8_lossy(preview),
+                    "bodyLength": req.body.len(),
+                })
+                .into(),
+            })
+        })
+    }
+
+    /// Serve an index of all registered routes at `path`, available only with the `dev` feature.
+    /// The page is HTML when the client accepts `text/html`, otherwise JSON.
+    /// It is built on every request, so routes registered later are listed too.
+    /// # Examples
+    ///
+    /// ``` rust
+    /// use pluto::router::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.dev_routes_page("/__routes");
+    /// ```
+    #[cfg(feature = "dev")]
+    pub fn dev_routes_page(&mut self, path: &str) -> &mut Self {
+        if !path.starts_with('/') {
+            panic!("expect path beginning with '/', found: '{}'", path);
+        }
+        let mut global_path = self.prefix.to_owned() + path;
+        if global_path.ends_with('/') {
+            global_path.pop();
+        }
+        self.dev_routes_path = Some(global_path);
+        self
+    }
+
+    /// Build the routes index if `path` is the path of the dev routes page.
+    #[cfg(feature = "dev")]
+    pub(crate) fn dev_routes_response(
+        &self,
+        path: &str,
+        req: &HttpRequest,
+    ) -> Option<HttpResponse> {
+        if self.dev_routes_path.as_deref() != Some(path) {
+            return None;
+        }
+        let routes = self.routes();
+        let wants_html = req
+            .header("Accept")
+            .is_some_and(|accept| accept.contains("text/html"));
+        if !wants_html {
+            let list: Vec<serde_json::Value> = routes
+                .iter()
+                .map(
+                    |(method, path)| serde_json::json!({ "method": method.as_ref(), "path": path }),
+                )
+                .collect();
+            return Some(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: serde_json::Value::Array(list).into(),
+            });
+        }
+        let escape = |text: &str| {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        };
+        let rows: String = routes
+            .iter()
+            .map(|(method, path)| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    method.as_ref(),
+                    escape(path)
+                )
+            })
+            .collect();
+        Some(HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::from([(
+                "Content-Type".to_string(),
+                "text/html; charset=utf-8".to_string(),
+            )]),
+            body: format!(
+                "<!DOCTYPE html><html><head><title>Routes</title></head><body>\
+                 <table><tr><th>Method</th><th>Path</th></tr>{}</table></body></html>",
+                rows
+            )
+            .into(),
+        })
+    }
+}
+
+/// Translate `:name` and `*name` segments to the `{name}` and `{*name}` syntax of the matcher.
+fn translate_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':').filter(|name| !name.is_empty()) {
+                return format!("{{{}}}", name);
+            }
+            if let Some(name) = segment.strip_prefix('*').filter(|name| !name.is_empty()) {
+                return format!("{{*{}}}", name);
+            }
+            segment.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The pattern reported as `HttpRequest::matched_pattern` for a route registered under `path`.
+fn route_pattern(path: &str) -> Option<String> {
+    match path {
+        "" => Some(String::from("/")),
+        path => Some(path.to_string()),
+    }
+}
+
+/// The name of the trailing `{*name}` segment of a translated path, if any.
+fn catch_all_name(path: &str) -> Option<String> {
+    path.rsplit('/')
+        .next()
+        .and_then(|segment| segment.strip_prefix("{*"))
+        .and_then(|segment| segment.strip_suffix('}'))
+        .map(str::to_string)
 }
 
 clone_trait_object!(Handler);
@@ -492,6 +1502,76 @@ where
     }
 }
 
+/// A handler wrapper applying a transformation to the successful responses of the inner handler.
+/// It is lighter than a middleware for one-off transformations of a single route.
+#[derive(Clone)]
+pub struct Then {
+    handler: Box<dyn Handler>,
+    after: fn(HttpResponse) -> HttpResponse,
+}
+
+impl Then {
+    /// Wrap `handler` so that `after` is applied to its responses with a non-error status code.
+    pub fn new(handler: impl Handler + 'static, after: fn(HttpResponse) -> HttpResponse) -> Self {
+        Self {
+            handler: Box::new(handler),
+            after,
+        }
+    }
+}
+
+impl Handler for Then {
+    fn handle(
+        &self,
+        req: HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<HttpResponse, HttpResponse>> + Send + Sync>> {
+        let res = self.handler.handle(req);
+        let after = self.after;
+        Box::pin(async move {
+            match res.await {
+                Ok(res) if res.status_code < 400 => Ok(after(res)),
+                res => res,
+            }
+        })
+    }
+}
+
+/// A helper macro for defining a handler with a typed body and typed path params.
+///
+/// The body is parsed with `body_into_struct` and the params with `params_into_struct`,
+/// a parsing failure short-circuits with the 400 response of the failing extractor.
+/// The request itself stays available under the third name.
+///
+/// # Example
+///
+/// ```ignore
+/// router.post("/users/{id}", false, handler!(|body: NewUser, params: UserParams, req| async move {
+///     Ok(HttpResponse {
+///         status_code: 201,
+///         headers: HeaderMap::new(),
+///         body: json!({ "id": params.id, "name": body.name }).into(),
+///     })
+/// }));
+/// ```
+#[macro_export]
+macro_rules! handler {
+    (
+        |$body:ident : $body_ty:ty, $params:ident : $params_ty:ty, $req:ident| $fut:expr
+    ) => {
+        move |req: $crate::http::HttpRequest| {
+            let parsed = req.body_into_struct::<$body_ty>().and_then(|body| {
+                req.params_into_struct::<$params_ty>()
+                    .map(|params| (body, params))
+            });
+            async move {
+                let ($body, $params) = parsed?;
+                let $req = req;
+                $fut.await
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod test {
     use serde_json::json;
@@ -506,7 +1586,7 @@ mod test {
         router.get("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from GET",
@@ -517,7 +1597,7 @@ mod test {
         router.post("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from POST",
@@ -528,7 +1608,7 @@ mod test {
         router.put("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from PUT",
@@ -539,7 +1619,7 @@ mod test {
         router.patch("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from PATCH",
@@ -550,7 +1630,7 @@ mod test {
         router.delete("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from DELETE",
@@ -561,7 +1641,7 @@ mod test {
         router.head("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from HEAD",
@@ -572,7 +1652,7 @@ mod test {
         router.options("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from OPTIONS",
@@ -604,7 +1684,7 @@ mod test {
         router.get("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from GET",
@@ -615,7 +1695,7 @@ mod test {
         router.post("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from POST",
@@ -626,7 +1706,7 @@ mod test {
         router.put("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from PUT",
@@ -637,7 +1717,7 @@ mod test {
         router.patch("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from PATCH",
@@ -648,7 +1728,7 @@ mod test {
         router.delete("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from DELETE",
@@ -659,7 +1739,7 @@ mod test {
         router.head("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from HEAD",
@@ -670,7 +1750,7 @@ mod test {
         router.options("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "statusCode": 200,
                     "message": "Hello World from OPTIONS",
@@ -695,12 +1775,506 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_conditional_registration() {
+        let mut router = Router::new();
+        let handler = |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({}).into(),
+            })
+        };
+        router.get_if(false, "/debug", false, handler);
+        router.get_if(true, "/enabled", false, handler);
+        router.handle_if(false, "/enabled", false, Method::POST, handler);
+
+        assert!(router.lookup(Method::GET, "/debug").is_err());
+        assert!(router.allowed("/debug").is_empty());
+        assert!(router.lookup(Method::GET, "/enabled").is_ok());
+        assert!(router.lookup(Method::POST, "/enabled").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_candid() {
+        let did = "service : { greet : (text) -> (text) query }";
+        let mut router = Router::new();
+        router.candid(did);
+
+        let lookup = router.lookup(Method::GET, "/candid.did").unwrap();
+        let res = lookup
+            .value
+            .handler
+            .handle(crate::http::RawHttpRequest::test("GET", "/candid.did", &[], &[]).into())
+            .await
+            .unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            res.headers.get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.body, did.to_string().into());
+    }
+
+    #[tokio::test]
+    async fn test_get_then() {
+        fn after(mut res: HttpResponse) -> HttpResponse {
+            res.add_raw_header("X-After", "true".to_string());
+            res
+        }
+        let mut router = Router::new();
+        router.get_then(
+            "/ok",
+            false,
+            |_req: HttpRequest| async move {
+                Ok(HttpResponse {
+                    status_code: 200,
+                    headers: HeaderMap::new(),
+                    body: json!({}).into(),
+                })
+            },
+            after,
+        );
+        router.get_then(
+            "/error",
+            false,
+            |_req: HttpRequest| async move {
+                Err(HttpResponse {
+                    status_code: 400,
+                    headers: HeaderMap::new(),
+                    body: json!({}).into(),
+                })
+            },
+            after,
+        );
+
+        let request = || crate::http::RawHttpRequest::test("GET", "/", &[], &[]).into();
+        let lookup = router.lookup(Method::GET, "/ok").unwrap();
+        let res = lookup.value.handler.handle(request()).await.unwrap();
+        assert_eq!(res.headers.get("X-After").unwrap(), "true");
+
+        let lookup = router.lookup(Method::GET, "/error").unwrap();
+        let res = lookup.value.handler.handle(request()).await.unwrap_err();
+        assert_eq!(res.headers.get("X-After"), None);
+    }
+
+    #[cfg(feature = "dev")]
+    #[tokio::test]
+    async fn test_echo() {
+        let mut router = Router::new();
+        router.echo("/debug/echo");
+        let app = crate::http::HttpServe::new_with_router(router.clone(), "http_request");
+        let req = crate::http::RawHttpRequest::test(
+            "POST",
+            "/debug/echo?page=2",
+            &[("X-Request-Id", "abc")],
+            b"hello",
+        );
+        let res = app.serve(req).await;
+        let body: serde_json::Value = serde_json::from_slice(&res.body).unwrap();
+        assert_eq!(body["method"], "POST");
+        assert_eq!(body["path"], "/debug/echo");
+        assert_eq!(body["query"], json!({ "page": "2" }));
+        assert_eq!(body["headers"], json!([["X-Request-Id", "abc"]]));
+        assert_eq!(body["body"], "hello");
+        assert_eq!(body["bodyLength"], 5);
+
+        let app = crate::http::HttpServe::new_with_router(router, "http_request");
+        let req = crate::http::RawHttpRequest::test("HEAD", "/debug/echo", &[], &[]);
+        let res = app.serve(req).await;
+        assert_eq!(res.status_code, 200);
+        assert!(res.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mount() {
+        let echo = |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "path": req.relative_path(), "params": req.params }).into(),
+            })
+        };
+        let mut users = Router::new();
+        users.get("/", false, echo).get("/:id", false, echo);
+        let mut posts = Router::new();
+        posts.post("/", false, echo);
+
+        let mut router = Router::new();
+        router.set_global_prefix("/api".to_string());
+        router.mount("/users", users).mount("/posts", posts);
+        let mut routes = router.routes();
+        routes.sort_by_key(|(_, path)| *path);
+        assert_eq!(
+            routes,
+            vec![
+                (&Method::POST, "/api/posts"),
+                (&Method::GET, "/api/users"),
+                (&Method::GET, "/api/users/{id}"),
+            ]
+        );
+
+        let app = crate::http::HttpServe::new_with_router(router, "http_request");
+        let res = app
+            .serve(crate::http::RawHttpRequest::test(
+                "GET",
+                "/api/users/7",
+                &[],
+                &[],
+            ))
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(&res.body).unwrap();
+        assert_eq!(body, json!({ "path": "/7", "params": { "id": "7" } }));
+    }
+
+    #[test]
+    fn test_prefix_trailing_slash() {
+        let handler = |_req: HttpRequest| async move { Ok(HttpResponse::no_content()) };
+        for prefix in ["/api", "/api/"] {
+            let mut users = Router::new();
+            users.get("/", false, handler).get("/:id", false, handler);
+            let mut router = Router::new();
+            router.set_global_prefix(prefix.to_string());
+            router
+                .get("/users", false, handler)
+                .get("/", false, handler);
+            router.mount("/posts/", users);
+            assert_eq!(
+                router.routes(),
+                vec![
+                    (&Method::GET, "/api/users"),
+                    (&Method::GET, "/api"),
+                    (&Method::GET, "/api/posts"),
+                    (&Method::GET, "/api/posts/{id}"),
+                ],
+                "{}",
+                prefix
+            );
+        }
+
+        let mut users = Router::new();
+        users.get("/", false, handler);
+        let mut router = Router::new();
+        router.set_global_prefix("/".to_string());
+        router.get("/users", false, handler).mount("/", users);
+        assert_eq!(
+            router.routes(),
+            vec![(&Method::GET, "/users"), (&Method::GET, "")]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot mount GET '/users/{id}' under '/users'")]
+    fn test_mount_conflict() {
+        let handler = |_req: HttpRequest| async move { Ok(HttpResponse::no_content()) };
+        let mut users = Router::new();
+        users.get("/:id", false, handler);
+        let mut router = Router::new();
+        router.get("/users/:id", false, handler);
+        router.mount("/users", users);
+    }
+
+    #[tokio::test]
+    async fn test_remove_and_replace() {
+        let handler = |req: HttpRequest| async move { Ok(HttpResponse::css(req.path)) };
+        let mut router = Router::new();
+        router
+            .get("/a", false, handler)
+            .get("/b/:id", false, handler)
+            .post("/b/:id", false, handler)
+            .get("/c", false, handler);
+
+        assert!(router.remove(Method::GET, "/b/:id"));
+        assert!(!router.remove(Method::GET, "/b/:id"));
+        assert!(!router.remove(Method::DELETE, "/a"));
+        assert!(router.lookup(Method::GET, "/b/1").is_err());
+        assert_eq!(router.allowed("/b/1"), vec!["POST", "OPTIONS"]);
+
+        router.get("/b/{name}", false, handler);
+        router.replace("/a", false, Method::GET, |_req: HttpRequest| async move {
+            Ok(HttpResponse::no_content())
+        });
+        router.replace("/d", false, Method::GET, handler);
+        let app = || crate::http::HttpServe::new_with_router(router.clone(), "http_request");
+        let get = |url: &str| crate::http::RawHttpRequest::test("GET", url, &[], &[]);
+        assert_eq!(app().serve(get("/a")).await.status_code, 204);
+        assert_eq!(app().serve(get("/b/1")).await.body, b"/b/1".to_vec());
+        // Routes registered after the removed one are still reachable
+        assert_eq!(app().serve(get("/c")).await.body, b"/c".to_vec());
+        assert_eq!(app().serve(get("/d")).await.body, b"/d".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_finalize() {
+        let mut router = Router::new();
+        router.get("/report/:id", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse::css("body {}".to_string()))
+        });
+        router.get("/other", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse::css("body {}".to_string()))
+        });
+        router.finalize(Method::GET, "/report/:id", |res: &mut ResponseContext| {
+            let checksum: u32 = res.body.iter().map(|byte| *byte as u32).sum();
+            let value = format!("{}:{}", res.status_code, checksum);
+            res.set_header("X-Checksum", value);
+        });
+
+        let serve = |url: &'static str| {
+            let app = crate::http::HttpServe::new_with_router(router.clone(), "http_request");
+            app.serve(crate::http::RawHttpRequest::test("GET", url, &[], &[]))
+        };
+        let expected: u32 = b"body {}".iter().map(|byte| *byte as u32).sum();
+        let res = serve("/report/1").await;
+        assert_eq!(
+            res.headers.get("X-Checksum").unwrap(),
+            &format!("200:{}", expected)
+        );
+        let res = serve("/other").await;
+        assert_eq!(res.headers.get("X-Checksum"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no route registered for POST '/missing'")]
+    fn test_finalize_unknown_route() {
+        Router::new().finalize(Method::POST, "/missing", |_res: &mut ResponseContext| {});
+    }
+
+    #[tokio::test]
+    async fn test_all() {
+        let mut router = Router::new();
+        router.all("/proxy", false, |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "method": req.method }).into(),
+            })
+        });
+
+        for method in [Method::GET, Method::POST, Method::DELETE] {
+            let req = crate::http::RawHttpRequest::test(method.as_str(), "/proxy", &[], &[]);
+            let lookup = router.lookup(method.clone(), "/proxy").unwrap();
+            let res = lookup.value.handler.handle(req.into()).await.unwrap();
+            assert_eq!(res.body, json!({ "method": method.as_str() }).into());
+        }
+        assert!(router.lookup(Method::HEAD, "/proxy").is_err());
+        let mut allowed = router.allowed("/proxy");
+        allowed.sort();
+        assert_eq!(
+            allowed,
+            vec!["DELETE", "GET", "HEAD", "OPTIONS", "PATCH", "POST", "PUT"]
+        );
+
+        router.all("/status", false, |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "status": "up" }).into(),
+            })
+        });
+        let serve = |method: &str| {
+            let app = crate::http::HttpServe::new_with_router(router.clone(), "http_request");
+            app.serve(crate::http::RawHttpRequest::test(
+                method,
+                "/status",
+                &[],
+                &[],
+            ))
+        };
+        let get = serve("GET").await;
+        let head = serve("HEAD").await;
+        assert_eq!(head.status_code, 200);
+        assert!(head.body.is_empty());
+        assert_eq!(
+            head.headers.get("Content-Length").unwrap(),
+            &get.body.len().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_and_connect() {
+        let handler = |req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "method": req.method }).into(),
+            })
+        };
+        let mut router = Router::new();
+        router.trace("/echo", false, handler);
+        router.connect("/echo", false, handler);
+
+        for method in [Method::TRACE, Method::CONNECT] {
+            let req = crate::http::RawHttpRequest::test(method.as_str(), "/echo", &[], &[]);
+            let lookup = router.lookup(method.clone(), "/echo").unwrap();
+            let res = lookup.value.handler.handle(req.into()).await.unwrap();
+            assert_eq!(res.body, json!({ "method": method.as_str() }).into());
+        }
+        let mut allowed = router.allowed("/echo");
+        allowed.sort();
+        assert_eq!(allowed, vec!["CONNECT", "OPTIONS", "TRACE"]);
+    }
+
+    #[tokio::test]
+    async fn test_on() {
+        let mut router = Router::new();
+        router.on(
+            "/search",
+            false,
+            &[Method::GET, Method::POST],
+            |req: HttpRequest| async move {
+                Ok(HttpResponse {
+                    status_code: 200,
+                    headers: HeaderMap::new(),
+                    body: json!({ "method": req.method }).into(),
+                })
+            },
+        );
+
+        for method in [Method::GET, Method::POST] {
+            let req = crate::http::RawHttpRequest::test(method.as_str(), "/search", &[], &[]);
+            let lookup = router.lookup(method.clone(), "/search").unwrap();
+            let res = lookup.value.handler.handle(req.into()).await.unwrap();
+            assert_eq!(res.body, json!({ "method": method.as_str() }).into());
+        }
+        assert!(router.lookup(Method::PUT, "/search").is_err());
+        let mut allowed = router.allowed("/search");
+        allowed.sort();
+        assert_eq!(allowed, vec!["GET", "HEAD", "OPTIONS", "POST"]);
+
+        router.auto_head(false);
+        let mut allowed = router.allowed("/search");
+        allowed.sort();
+        assert_eq!(allowed, vec!["GET", "OPTIONS", "POST"]);
+    }
+
+    #[tokio::test]
+    async fn test_handler_macro() {
+        #[derive(serde::Deserialize)]
+        struct Body {
+            name: String,
+        }
+        #[derive(serde::Deserialize)]
+        struct Params {
+            id: String,
+        }
+
+        let handler = crate::handler!(|body: Body, params: Params, req| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({ "id": params.id, "name": body.name, "path": req.path }).into(),
+            })
+        });
+        let request = |body: &[u8], params: &[(&str, &str)]| {
+            let mut req: HttpRequest =
+                crate::http::RawHttpRequest::test("POST", "/users/7", &[], body).into();
+            req.path = String::from("/users/7");
+            req.params = params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            req
+        };
+
+        let res = handler
+            .handle(request(br#"{"name":"pluto"}"#, &[("id", "7")]))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.body,
+            json!({ "id": "7", "name": "pluto", "path": "/users/7" }).into()
+        );
+
+        let res = handler
+            .handle(request(b"not json", &[("id", "7")]))
+            .await
+            .unwrap_err();
+        assert_eq!(res.status_code, 400);
+
+        let res = handler
+            .handle(request(br#"{"name":"pluto"}"#, &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(res.status_code, 400);
+    }
+
+    #[test]
+    fn test_path_syntax() {
+        assert_eq!(translate_path("/users/:id"), "/users/{id}");
+        assert_eq!(translate_path("/files/*rest"), "/files/{*rest}");
+        assert_eq!(translate_path("/a/{b}/{*c}"), "/a/{b}/{*c}");
+        assert_eq!(translate_path("/time/12:30"), "/time/12:30");
+
+        let handler = |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({}).into(),
+            })
+        };
+        let mut router = Router::new();
+        router.get("/users/{id}", false, handler);
+        router.get("/files/{*rest}", false, handler);
+        router.get("/posts/:post/comments/:comment", false, handler);
+        router.get("/assets/*path", false, handler);
+
+        let params = |path: &str| -> Vec<(String, String)> {
+            let lookup = router.lookup(Method::GET, path).unwrap();
+            lookup
+                .params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(params("/users/7"), vec![("id".into(), "7".into())]);
+        assert_eq!(
+            params("/files/a/b.txt"),
+            vec![("rest".into(), "a/b.txt".into())]
+        );
+        assert_eq!(
+            params("/posts/1/comments/2"),
+            vec![("post".into(), "1".into()), ("comment".into(), "2".into())]
+        );
+        assert_eq!(
+            params("/assets/css/app.css"),
+            vec![("path".into(), "css/app.css".into())]
+        );
+    }
+
+    #[test]
+    fn test_for_each_route() {
+        let mut router = Router::new();
+        let handler = |_req: HttpRequest| async move {
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HeaderMap::new(),
+                body: json!({}).into(),
+            })
+        };
+        router.get("/users", false, handler);
+        router.post("/users", false, handler);
+        router.get("/users/{id}", true, handler);
+
+        let mut routes = Vec::new();
+        router.for_each_route(|method, path, container| {
+            routes.push((method.to_string(), path.to_string(), container.upgrade()));
+        });
+        assert_eq!(
+            routes,
+            vec![
+                ("GET".to_string(), "/users".to_string(), false),
+                ("POST".to_string(), "/users".to_string(), false),
+                ("GET".to_string(), "/users/{id}".to_string(), true),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_lookup_works() {
         let mut router = Router::new();
         let response = HttpResponse {
             status_code: 200,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: json!({
                 "message": "Hello World from GET",
             })
@@ -709,7 +2283,7 @@ mod test {
         router.get("/hello", false, |_req: HttpRequest| async move {
             Ok(HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "message": "Hello World from GET",
                 })
@@ -727,6 +2301,7 @@ mod test {
                     url: "http:://localhost:8080/hello".to_string(),
                     headers: Vec::new(),
                     body: Vec::new(),
+                    certificate_version: None,
                 }
                 .into(),
             )
@@ -737,7 +2312,7 @@ mod test {
             result,
             HttpResponse {
                 status_code: 200,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: json!({
                     "message": "Hello World from GET",
                 })
@@ -0,0 +1,256 @@
+use serde_json::{json, Value};
+
+use crate::http::{HeaderMap, HttpResponse};
+
+/// A violation of a JSON schema, located by a JSON pointer into the validated value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Validate `value` against a JSON schema and collect all violations.
+///
+/// A practical subset of JSON Schema is supported: `type` (a name or a list of names),
+/// `enum`, `const`, `required`, `properties`, `additionalProperties: false`, `items`,
+/// `minItems`/`maxItems`, `minLength`/`maxLength` and `minimum`/`maximum`.
+/// Other keywords are ignored.
+///
+/// # Examples
+///
+/// ``` rust
+/// use pluto::schema::validate;
+/// use serde_json::json;
+///
+/// let schema = json!({ "type": "object", "required": ["name"] });
+/// assert!(validate(&schema, &json!({ "name": "pluto" })).is_empty());
+/// assert_eq!(validate(&schema, &json!({}))[0].path, "/name");
+/// ```
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaError> {
+    let mut errors = Vec::new();
+    validate_at(schema, value, "", &mut errors);
+    errors
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+            push(
+                errors,
+                path,
+                format!(
+                    "expected {}, found {}",
+                    names.join(" or "),
+                    type_name(value)
+                ),
+            );
+            // The remaining keywords assume the right type
+            return;
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            push(
+                errors,
+                path,
+                String::from("is not one of the allowed values"),
+            );
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            push(errors, path, format!("must be equal to {}", constant));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        push(errors, &pointer(path, key), String::from("is required"));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (key, item) in object {
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(item_schema) => {
+                        validate_at(item_schema, item, &pointer(path, key), errors)
+                    }
+                    None if closed => {
+                        push(errors, &pointer(path, key), String::from("is not allowed"))
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if len < min {
+                    push(errors, path, format!("must have at least {} items", min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if len > max {
+                    push(errors, path, format!("must have at most {} items", max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(
+                        item_schema,
+                        item,
+                        &pointer(path, &index.to_string()),
+                        errors,
+                    );
+                }
+            }
+        }
+        Value::String(string) => {
+            let len = string.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    push(
+                        errors,
+                        path,
+                        format!("must be at least {} characters long", min),
+                    );
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    push(
+                        errors,
+                        path,
+                        format!("must be at most {} characters long", max),
+                    );
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or(f64::NAN);
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    push(
+                        errors,
+                        path,
+                        format!("must be greater than or equal to {}", min),
+                    );
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    push(
+                        errors,
+                        path,
+                        format!("must be less than or equal to {}", max),
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn push(errors: &mut Vec<SchemaError>, path: &str, message: String) {
+    errors.push(SchemaError {
+        path: path.to_string(),
+        message,
+    });
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => type_name(value) == name,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Append a reference token to a JSON pointer, escaping it as described in RFC 6901.
+fn pointer(path: &str, token: &str) -> String {
+    format!("{}/{}", path, token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Build the 422 Unprocessable Entity response listing the schema violations.
+pub(crate) fn validation_error(errors: Vec<SchemaError>) -> HttpResponse {
+    let errors: Vec<Value> = errors
+        .into_iter()
+        .map(|error| json!({ "path": error.path, "message": error.message }))
+        .collect();
+    HttpResponse {
+        status_code: 422,
+        headers: HeaderMap::new(),
+        body: json!({
+            "statusCode": 422,
+            "message": "The body does not match the schema",
+            "error": "Unprocessable Entity",
+            "errors": errors,
+        })
+        .into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["name", "age"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "role": { "enum": ["admin", "user"] },
+                "tags": { "type": "array", "items": { "type": "string" } },
+            }
+        })
+    }
+
+    #[test]
+    fn test_valid() {
+        let value = json!({ "name": "pluto", "age": 3, "role": "admin", "tags": ["a"] });
+        assert_eq!(validate(&schema(), &value), Vec::new());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let value = json!({ "name": "", "age": 1.5, "tags": ["a", 2], "extra": true });
+        let errors: Vec<(String, String)> = validate(&schema(), &value)
+            .into_iter()
+            .map(|error| (error.path, error.message))
+            .collect();
+        assert!(errors.contains(&("/name".into(), "must be at least 1 characters long".into())));
+        assert!(errors.contains(&("/age".into(), "expected integer, found number".into())));
+        assert!(errors.contains(&("/tags/1".into(), "expected string, found number".into())));
+        assert!(errors.contains(&("/extra".into(), "is not allowed".into())));
+        assert_eq!(errors.len(), 4);
+
+        let errors = validate(&schema(), &json!({ "role": "guest" }));
+        let paths: Vec<&str> = errors.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(paths, vec!["/name", "/age", "/role"]);
+
+        let errors = validate(&schema(), &json!([]));
+        assert_eq!(errors[0].message, "expected object, found array");
+    }
+}
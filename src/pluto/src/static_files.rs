@@ -1,7 +1,275 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use crate::{
+    cache_control::CacheControl,
+    certification,
+    encoding::AcceptEncoding,
+    http::{HeaderField, HeaderMap, HttpBody, HttpRequest, HttpResponse, RawHttpRequest},
+};
+
+/// Content codings of precompressed sidecars, in the order the server prefers them.
+const SIDECAR_ENCODINGS: [&str; 2] = ["br", "gzip"];
+
+/// The first bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A static file ready to be served by a route, together with its precompressed sidecars.
+///
+/// Sidecars are alternative representations of the same file (e.g. `app.js.br` or `app.js.gz`).
+/// When the client announces support for them through the `Accept-Encoding` header, the best
+/// one is served instead of the original content.
+#[derive(Clone)]
+pub struct StaticAsset {
+    content: &'static [u8],
+    mime: String,
+    brotli: Option<&'static [u8]>,
+    gzip: Option<&'static [u8]>,
+    cache_control: Option<CacheControl>,
+}
+
+impl StaticAsset {
+    /// Create an asset from the raw file content and its mime type.
+    pub fn new(content: &'static [u8], mime: &str) -> Self {
+        Self {
+            content,
+            mime: mime.to_string(),
+            brotli: None,
+            gzip: None,
+            cache_control: None,
+        }
+    }
+
+    /// Set the brotli-compressed (`.br`) representation of the asset.
+    pub fn brotli(mut self, content: Option<&'static [u8]>) -> Self {
+        self.brotli = content;
+        self
+    }
+
+    /// Set the gzip-compressed (`.gz`) representation of the asset.
+    pub fn gzip(mut self, content: Option<&'static [u8]>) -> Self {
+        self.gzip = content;
+        self
+    }
+
+    /// Set the `Cache-Control` header sent with the asset, e.g. with `stale_while_revalidate`.
+    pub fn cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    fn sidecar(&self, encoding: &str) -> Option<&'static [u8]> {
+        match encoding {
+            "br" => self.brotli,
+            "gzip" => self.gzip,
+            _ => None,
+        }
+    }
+
+    /// Build the response for the given request.
+    /// Brotli is preferred over gzip when both are available and equally accepted by the client.
+    ///
+    /// Content compiled already gzipped is served as is to clients accepting gzip
+    /// and decompressed for the others, unless the mime type is a gzip archive.
+    ///
+    /// Every representation gets its own `ETag`, and requests revalidating it with `If-None-Match`
+    /// get a `304 Not Modified`, see `HttpResponse::with_etag`.
+    ///
+    /// A single `Range: bytes=start-end` (or `start-`, `-suffix`) is answered with a `206 Partial Content`
+    /// slice of the representation, e.g. for seeking in audio and video, and unsatisfiable ranges get a `416`.
+    /// Requests for several ranges at once get the whole file.
+    pub fn serve(&self, req: &HttpRequest) -> HttpResponse {
+        let mut res = self.representation(req).with_etag(req);
+        if res.status_code != 200 {
+            return res;
+        }
+        res.add_raw_header("Accept-Ranges", "bytes".to_string());
+        match req.header("Range") {
+            Some(range) => Self::partial(res, range),
+            None => res,
+        }
+    }
+
+    fn partial(mut res: HttpResponse, range: &str) -> HttpResponse {
+        let body: Vec<u8> = res.body.clone().into();
+        match parse_range(range, body.len()) {
+            Some(Ok((start, end))) => {
+                res.status_code = 206;
+                res.add_raw_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, body.len()),
+                );
+                res.body = HttpBody::Raw(body[start..=end].to_vec());
+            }
+            Some(Err(())) => {
+                res.status_code = 416;
+                res.remove_header("Content-Encoding");
+                res.add_raw_header("Content-Range", format!("bytes */{}", body.len()));
+                res.body = HttpBody::Raw(Vec::new());
+            }
+            None => {}
+        }
+        res
+    }
+
+    /// Certify every representation of the asset served at `path`, see `certification::certify`.
+    pub fn certify(&self, path: &str) {
+        let mut accept_encodings = vec![None];
+        accept_encodings.extend(SIDECAR_ENCODINGS.map(Some));
+        for accept_encoding in accept_encodings {
+            let headers: Vec<HeaderField> = accept_encoding
+                .map(|encoding| HeaderField("Accept-Encoding".to_string(), encoding.to_string()))
+                .into_iter()
+                .collect();
+            let req: HttpRequest = RawHttpRequest {
+                method: "GET".to_string(),
+                url: path.to_string(),
+                headers,
+                body: Vec::new(),
+                certificate_version: Some(2),
+            }
+            .into();
+            certification::certify(path, &self.serve(&req));
+        }
+    }
+
+    fn representation(&self, req: &HttpRequest) -> HttpResponse {
+        let mut headers = HeaderMap::from([("Content-Type".to_string(), self.mime.clone())]);
+        if let Some(ref cache_control) = self.cache_control {
+            headers.insert("Cache-Control".to_string(), cache_control.to_string());
+        }
+        if self.is_gzipped() {
+            return self.serve_gzipped(req, headers);
+        }
+        let available: Vec<&str> = SIDECAR_ENCODINGS
+            .into_iter()
+            .filter(|encoding| self.sidecar(encoding).is_some())
+            .collect();
+        if available.is_empty() {
+            return HttpResponse {
+                status_code: 200,
+                headers,
+                body: self.plain_body(self.content),
+            };
+        }
+
+        headers.insert("Vary".to_string(), "Accept-Encoding".to_string());
+        let sidecar = req
+            .header("Accept-Encoding")
+            .and_then(|header| AcceptEncoding::parse(header).preferred(&available))
+            .and_then(|encoding| self.sidecar(encoding).map(|content| (encoding, content)));
+        let body = match sidecar {
+            Some((encoding, content)) => {
+                headers.insert("Content-Encoding".to_string(), encoding.to_string());
+                HttpBody::Raw(content.to_vec())
+            }
+            None => self.plain_body(self.content),
+        };
+        HttpResponse {
+            status_code: 200,
+            headers,
+            body,
+        }
+    }
+
+    fn is_gzipped(&self) -> bool {
+        let essence = self.mime.split(';').next().unwrap_or("").trim();
+        let archive = ["application/gzip", "application/x-gzip"]
+            .iter()
+            .any(|mime| essence.eq_ignore_ascii_case(mime));
+        !archive && self.content.starts_with(&GZIP_MAGIC)
+    }
+
+    fn serve_gzipped(&self, req: &HttpRequest, mut headers: HeaderMap) -> HttpResponse {
+        headers.insert("Vary".to_string(), "Accept-Encoding".to_string());
+        let accepts_gzip = req
+            .header("Accept-Encoding")
+            .is_some_and(|header| AcceptEncoding::parse(header).accepts("gzip"));
+        let mut decompressed = Vec::new();
+        let body = if accepts_gzip {
+            headers.insert("Content-Encoding".to_string(), "gzip".to_string());
+            HttpBody::Raw(self.content.to_vec())
+        } else if GzDecoder::new(self.content)
+            .read_to_end(&mut decompressed)
+            .is_ok()
+        {
+            self.plain_body(&decompressed)
+        } else {
+            // Not a valid gzip stream after all, serve the bytes untouched
+            HttpBody::Raw(self.content.to_vec())
+        };
+        HttpResponse {
+            status_code: 200,
+            headers,
+            body,
+        }
+    }
+
+    fn plain_body(&self, content: &[u8]) -> HttpBody {
+        let essence = self.mime.split(';').next().unwrap_or("").trim();
+        let (kind, subtype) = essence.split_once('/').unwrap_or((essence, ""));
+        if kind == "text" || subtype == "json" {
+            if let Ok(text) = String::from_utf8(content.to_vec()) {
+                return HttpBody::String(text);
+            }
+        }
+        HttpBody::Raw(content.to_vec())
+    }
+}
+
+/// Parse a single byte range against a body of `len` bytes into inclusive `(start, end)` offsets.
+/// Returns `None` for headers that should be ignored (other units, several ranges or malformed values)
+/// and `Some(Err(()))` for unsatisfiable ranges.
+fn parse_range(header: &str, len: usize) -> Option<Result<(usize, usize), ()>> {
+    let (unit, range) = header.trim().split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") || range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.trim().split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // The last `end` bytes
+        let suffix: usize = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = match end {
+            "" => len.saturating_sub(1),
+            end => end.parse::<usize>().ok()?.min(len.saturating_sub(1)),
+        };
+        if start >= len {
+            return Some(Err(()));
+        }
+        if end < start {
+            return None;
+        }
+        (start, end)
+    };
+    Some(Ok(range))
+}
+
 /// The main way to load the static files as ready-to-use routes in the application.
 ///
 /// This ensures every file is accessible through HTTP GET requests by adding all of them to the router automatically.
 ///
+/// Files with a `.br` or `.gz` extension whose original file is also present (e.g. `app.js.br` next to `app.js`)
+/// are not registered as separate routes. Instead they are served from the original path to the clients that
+/// accept the given encoding, with brotli being preferred over gzip.
+///
+/// An optional `CacheControl` is sent with every file, see also `use_static_files_with_cache!`.
+///
+/// Every file is certified for the HTTP response verification, see `certification::certify`.
+///
+/// Every file is sent with an `ETag`, so browsers revalidating a cached file get an empty `304 Not Modified`.
+/// Both work together: while a file is fresh according to `max-age` the browser does not ask the canister
+/// at all, once it is stale the browser revalidates it with `If-None-Match` and downloads it again only
+/// if it changed. With `immutable` the browser skips revalidation even on reload, so use it only
+/// for files with a content hash in their name.
+///
 /// # Example
 ///
 /// The best way to use this macro is to include it in the bootstraping step for the router:
@@ -13,6 +281,9 @@
 ///     ROUTER.with(|r| {
 ///         let mut instance = controller::setup();
 ///         pluto::use_static_files!(instance);
+///         // or with caching
+///         let cache = CacheControl::new().public().max_age(3600).stale_while_revalidate(60);
+///         pluto::use_static_files!(instance, cache);
 ///         *r.borrow_mut() = instance;
 ///     })
 /// }
@@ -22,20 +293,248 @@ macro_rules! use_static_files {
     (
         $router:path
     ) => {
-        for file in crate::compiled::templates::statics::STATICS.iter() {
-            $router.get(&format!("/{}", file.name), false, |_req| async {
-                Ok(HttpResponse {
-                    status_code: 200,
-                    headers: HashMap::from([("Content-Type".to_string(), file.mime.to_string())]),
-                    body: if file.mime.type_() == "text" || file.mime.subtype() == "json" {
-                        ic_pluto::http::HttpBody::String(
-                            String::from_utf8(file.content.to_vec()).unwrap(),
-                        )
-                    } else {
-                        file.content.to_owned().into()
-                    },
-                })
+        ic_pluto::use_static_files!($router, None::<ic_pluto::cache_control::CacheControl>);
+    };
+    (
+        $router:path, $cache_control:expr
+    ) => {
+        let cache_control: Option<ic_pluto::cache_control::CacheControl> = $cache_control.into();
+        let statics = crate::compiled::templates::statics::STATICS;
+        for file in statics.iter() {
+            let is_sidecar = [".br", ".gz"].iter().any(|extension| {
+                file.name
+                    .strip_suffix(extension)
+                    .is_some_and(|original| statics.iter().any(|f| f.name == original))
+            });
+            if is_sidecar {
+                continue;
+            }
+            let sidecar = |extension: &str| {
+                statics
+                    .iter()
+                    .find(|f| f.name == format!("{}{}", file.name, extension))
+                    .map(|f| f.content)
+            };
+            let mut asset =
+                ic_pluto::static_files::StaticAsset::new(file.content, file.mime.as_ref())
+                    .brotli(sidecar(".br"))
+                    .gzip(sidecar(".gz"));
+            if let Some(ref cache_control) = cache_control {
+                asset = asset.cache_control(cache_control.clone());
+            }
+            let path = format!("/{}", file.name);
+            asset.certify(&format!("{}{}", $router.global_prefix(), path));
+            $router.get(&path, false, move |req| {
+                let res = asset.serve(&req);
+                async move { Ok(res) }
             });
         }
     };
 }
+
+/// Load the static files as routes like `use_static_files!`, sending them with a
+/// `Cache-Control: public, max-age=<max_age>` header.
+///
+/// For assets with a content hash in their name, a year long `immutable` policy saves even the revalidation:
+///
+/// ```rust
+/// let cache = CacheControl::new().public().max_age(31536000).immutable();
+/// pluto::use_static_files!(instance, cache);
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// #[post_upgrade]
+/// fn post_upgrade() {
+///     ROUTER.with(|r| {
+///         let mut instance = controller::setup();
+///         // Cache every file for an hour
+///         pluto::use_static_files_with_cache!(instance, 3600);
+///         *r.borrow_mut() = instance;
+///     })
+/// }
+/// ```
+#[macro_export]
+macro_rules! use_static_files_with_cache {
+    (
+        $router:path, $max_age:expr
+    ) => {
+        ic_pluto::use_static_files!(
+            $router,
+            ic_pluto::cache_control::CacheControl::new()
+                .public()
+                .max_age($max_age)
+        );
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::RawHttpRequest;
+
+    const PLAIN: &[u8] = b"console.log('plain')";
+    const BROTLI: &[u8] = b"brotli bytes";
+    const GZIP: &[u8] = b"gzip bytes";
+
+    fn request(accept_encoding: Option<&str>) -> HttpRequest {
+        let headers: Vec<(&str, &str)> = accept_encoding
+            .map(|value| vec![("Accept-Encoding", value)])
+            .unwrap_or_default();
+        RawHttpRequest::test("GET", "/app.js", &headers, &[]).into()
+    }
+
+    fn asset() -> StaticAsset {
+        StaticAsset::new(PLAIN, "text/javascript")
+            .brotli(Some(BROTLI))
+            .gzip(Some(GZIP))
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let cache = crate::cache_control::CacheControl::new()
+            .public()
+            .max_age(3600)
+            .stale_while_revalidate(60)
+            .stale_if_error(86400);
+        let res = asset().cache_control(cache).serve(&request(None));
+        assert_eq!(
+            res.headers.get("Cache-Control").unwrap(),
+            "public, max-age=3600, stale-while-revalidate=60, stale-if-error=86400"
+        );
+
+        let res = asset().serve(&request(None));
+        assert!(!res.headers.contains_key("Cache-Control"));
+    }
+
+    #[test]
+    fn test_etag() {
+        let res = asset().serve(&request(Some("br")));
+        let brotli_etag = res.headers.get("ETag").unwrap().clone();
+        let res = asset().serve(&request(None));
+        let plain_etag = res.headers.get("ETag").unwrap().clone();
+        assert_ne!(brotli_etag, plain_etag);
+
+        let req: HttpRequest = RawHttpRequest::test(
+            "GET",
+            "/app.js",
+            &[("Accept-Encoding", "br"), ("If-None-Match", &brotli_etag)],
+            &[],
+        )
+        .into();
+        let res = asset().serve(&req);
+        assert_eq!(res.status_code, 304);
+        assert_eq!(res.headers.get("Vary").unwrap(), "Accept-Encoding");
+        assert_eq!(res.body, HttpBody::Raw(Vec::new()));
+    }
+
+    #[test]
+    fn test_range() {
+        let range = |range: &str| -> HttpResponse {
+            let req: HttpRequest =
+                RawHttpRequest::test("GET", "/app.js", &[("Range", range)], &[]).into();
+            StaticAsset::new(PLAIN, "text/javascript").serve(&req)
+        };
+
+        let res = range("bytes=0-6");
+        assert_eq!(res.status_code, 206);
+        assert_eq!(res.headers.get("Content-Range").unwrap(), "bytes 0-6/20");
+        assert_eq!(res.headers.get("Accept-Ranges").unwrap(), "bytes");
+        assert_eq!(res.body, HttpBody::Raw(b"console".to_vec()));
+        let res = range("bytes=13-");
+        assert_eq!(res.headers.get("Content-Range").unwrap(), "bytes 13-19/20");
+        assert_eq!(res.body, HttpBody::Raw(b"plain')".to_vec()));
+        let res = range("bytes=-2");
+        assert_eq!(res.body, HttpBody::Raw(b"')".to_vec()));
+        let res = range("bytes=15-100");
+        assert_eq!(res.headers.get("Content-Range").unwrap(), "bytes 15-19/20");
+
+        for unsatisfiable in ["bytes=20-", "bytes=-0"] {
+            let res = range(unsatisfiable);
+            assert_eq!(res.status_code, 416);
+            assert_eq!(res.headers.get("Content-Range").unwrap(), "bytes */20");
+        }
+        for ignored in ["bytes=0-1,4-5", "items=0-1", "bytes=5-1", "bytes=a-b"] {
+            let res = range(ignored);
+            assert_eq!(res.status_code, 200);
+            assert_eq!(
+                res.body,
+                HttpBody::String(String::from_utf8(PLAIN.to_vec()).unwrap())
+            );
+        }
+
+        let res = asset().serve(&request(None));
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.headers.get("Accept-Ranges").unwrap(), "bytes");
+    }
+
+    #[test]
+    fn test_brotli_preferred() {
+        let res = asset().serve(&request(Some("gzip, deflate, br")));
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "br");
+        assert_eq!(res.headers.get("Content-Type").unwrap(), "text/javascript");
+        assert_eq!(res.headers.get("Vary").unwrap(), "Accept-Encoding");
+        assert_eq!(res.body, HttpBody::Raw(BROTLI.to_vec()));
+    }
+
+    #[test]
+    fn test_quality_ranking() {
+        let res = asset().serve(&request(Some("br;q=0.5, gzip;q=0.8")));
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(res.body, HttpBody::Raw(GZIP.to_vec()));
+    }
+
+    #[test]
+    fn test_gzip_fallback() {
+        let res = asset().serve(&request(Some("gzip")));
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+
+        let res = StaticAsset::new(PLAIN, "text/javascript")
+            .gzip(Some(GZIP))
+            .serve(&request(Some("br, gzip")));
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(res.body, HttpBody::Raw(GZIP.to_vec()));
+    }
+
+    #[test]
+    fn test_gzipped_content() {
+        let gzipped = crate::compression::CompressionConfig::new().compress(PLAIN);
+        let gzipped: &'static [u8] = Box::leak(gzipped.into_boxed_slice());
+        let asset = StaticAsset::new(gzipped, "text/javascript");
+
+        let res = asset.serve(&request(Some("gzip, br")));
+        assert_eq!(res.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(res.headers.get("Vary").unwrap(), "Accept-Encoding");
+        assert_eq!(res.body, HttpBody::Raw(gzipped.to_vec()));
+
+        for accept_encoding in [None, Some("br")] {
+            let res = asset.serve(&request(accept_encoding));
+            assert_eq!(res.headers.get("Content-Encoding"), None);
+            assert_eq!(
+                res.body,
+                HttpBody::String(String::from_utf8(PLAIN.to_vec()).unwrap())
+            );
+        }
+
+        let res = StaticAsset::new(gzipped, "application/gzip").serve(&request(None));
+        assert_eq!(res.headers.get("Content-Encoding"), None);
+        assert_eq!(res.body, HttpBody::Raw(gzipped.to_vec()));
+    }
+
+    #[test]
+    fn test_plain_fallback() {
+        for accept_encoding in [None, Some("identity"), Some("br;q=0, gzip;q=0")] {
+            let res = asset().serve(&request(accept_encoding));
+            assert_eq!(res.headers.get("Content-Encoding"), None);
+            assert_eq!(
+                res.body,
+                HttpBody::String(String::from_utf8(PLAIN.to_vec()).unwrap())
+            );
+        }
+
+        let res = StaticAsset::new(PLAIN, "application/octet-stream").serve(&request(Some("br")));
+        assert_eq!(res.headers.get("Vary"), None);
+        assert_eq!(res.body, HttpBody::Raw(PLAIN.to_vec()));
+    }
+}
@@ -0,0 +1,186 @@
+use candid::{define_function, CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// The size of a streamed chunk, kept under the IC message size limit (about 2 MB)
+/// with room left for the headers.
+pub const CHUNK_SIZE: usize = 1_900_000;
+
+/// Identifies the next chunk of a streamed body, passed back to the streaming callback.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StreamingToken {
+    /// The key the canister looks the content up with, e.g. the path of a static file
+    pub key: String,
+    /// The index of the chunk, the first chunk `0` is sent in the response itself
+    pub index: u64,
+}
+
+define_function!(pub StreamingCallback : (StreamingToken) -> (StreamingCallbackHttpResponse) query);
+
+/// The streaming strategy of a response, telling the gateway how to fetch the rest of the body.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub enum StreamingStrategy {
+    Callback {
+        callback: StreamingCallback,
+        token: StreamingToken,
+    },
+}
+
+/// A chunk returned by the streaming callback, with the token of the next chunk if there is one.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub struct StreamingCallbackHttpResponse {
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+    pub token: Option<StreamingToken>,
+}
+
+/// The first chunk of a streamed body together with what is needed to build its strategy,
+/// created with `HttpResponse::stream`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamingBody {
+    pub(crate) chunk: Vec<u8>,
+    pub(crate) token: Option<StreamingToken>,
+    pub(crate) canister_id: Principal,
+    pub(crate) callback: String,
+}
+
+impl StreamingBody {
+    /// Split `content` into the first chunk and the token of the second one.
+    pub(crate) fn new(content: &[u8], key: &str, callback: &str) -> Self {
+        Self {
+            chunk: chunk(content, 0).to_vec(),
+            token: next_token(content, key, 0),
+            canister_id: canister_id(),
+            callback: callback.to_string(),
+        }
+    }
+
+    /// Get the streaming strategy, `None` when the whole body fits in the first chunk.
+    pub(crate) fn strategy(&self) -> Option<StreamingStrategy> {
+        self.token.clone().map(|token| StreamingStrategy::Callback {
+            callback: StreamingCallback::new(self.canister_id, self.callback.clone()),
+            token,
+        })
+    }
+}
+
+/// Produce the chunk requested by the gateway, the companion of `HttpResponse::stream`.
+/// `content` looks up the streamed content by the key given to `HttpResponse::stream`,
+/// an unknown key or index results in an empty last chunk.
+///
+/// The content must be the same in every call, so it is usually static or stored in the canister state,
+/// since changes made during a query call are discarded.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[query]
+/// fn http_request_streaming_callback(token: StreamingToken) -> StreamingCallbackHttpResponse {
+///     pluto::streaming::http_request_streaming_callback(token, |key| {
+///         STATICS.iter().find(|file| file.name == key).map(|file| file.content)
+///     })
+/// }
+/// ```
+pub fn http_request_streaming_callback<'a>(
+    token: StreamingToken,
+    content: impl FnOnce(&str) -> Option<&'a [u8]>,
+) -> StreamingCallbackHttpResponse {
+    // The token comes from the client, an index not fitting in `usize` is past the end
+    let found = content(&token.key).zip(usize::try_from(token.index).ok());
+    let (content, index) = match found {
+        Some(found) => found,
+        None => {
+            return StreamingCallbackHttpResponse {
+                body: Vec::new(),
+                token: None,
+            }
+        }
+    };
+    StreamingCallbackHttpResponse {
+        body: chunk(content, index).to_vec(),
+        token: next_token(content, &token.key, index),
+    }
+}
+
+fn chunk(content: &[u8], index: usize) -> &[u8] {
+    let start = index.saturating_mul(CHUNK_SIZE).min(content.len());
+    let end = start.saturating_add(CHUNK_SIZE).min(content.len());
+    &content[start..end]
+}
+
+fn next_token(content: &[u8], key: &str, index: usize) -> Option<StreamingToken> {
+    let next = index.checked_add(1)?;
+    (next.saturating_mul(CHUNK_SIZE) < content.len()).then(|| StreamingToken {
+        key: key.to_string(),
+        index: next as u64,
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn canister_id() -> Principal {
+    ic_cdk::id()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn canister_id() -> Principal {
+    Principal::anonymous()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let content: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let body = StreamingBody::new(&content, "video.mp4", "http_request_streaming_callback");
+        assert_eq!(body.chunk, &content[..CHUNK_SIZE]);
+        let mut token = match body.strategy() {
+            Some(StreamingStrategy::Callback { callback, token }) => {
+                assert_eq!(callback.0.method, "http_request_streaming_callback");
+                token
+            }
+            None => panic!("expected a streaming strategy"),
+        };
+
+        let mut streamed = body.chunk.clone();
+        loop {
+            let res = http_request_streaming_callback(token, |key| {
+                (key == "video.mp4").then_some(content.as_slice())
+            });
+            streamed.extend(res.body);
+            token = match res.token {
+                Some(token) => token,
+                None => break,
+            };
+        }
+        assert_eq!(streamed, content);
+    }
+
+    #[test]
+    fn test_small_and_unknown() {
+        let body = StreamingBody::new(b"small", "a", "callback");
+        assert_eq!(body.chunk, b"small");
+        assert_eq!(body.strategy(), None);
+
+        let token = StreamingToken {
+            key: "missing".to_string(),
+            index: 1,
+        };
+        let res = http_request_streaming_callback(token, |_| None);
+        assert_eq!(res.body, Vec::<u8>::new());
+        assert_eq!(res.token, None);
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        let content = b"small";
+        let token = StreamingToken {
+            key: "a".to_string(),
+            index: u64::MAX,
+        };
+        let res = http_request_streaming_callback(token, |_| Some(content.as_slice()));
+        assert_eq!(res.body, Vec::<u8>::new());
+        assert_eq!(res.token, None);
+        assert_eq!(next_token(content, "a", usize::MAX), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::{Arc, Mutex};
+
+use crate::http::{HttpServe, RawHttpRequest};
+use crate::panic::CatchUnwind;
+use crate::router::Router;
+
+/// The outcome of the synthetic request sent to a single route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteCheck {
+    pub method: String,
+    /// The registered path pattern
+    pub path: String,
+    /// The path the request was sent to, with placeholders in place of the params
+    pub url: String,
+    /// The status code of the response, `None` if the handler panicked
+    pub status_code: Option<u16>,
+    /// The panic message, if the handler panicked
+    pub panic: Option<String>,
+}
+
+impl RouteCheck {
+    /// Check if the route answered without a panic or a server error.
+    /// Client errors are accepted, as the synthetic request has no body nor meaningful params.
+    pub fn is_ok(&self) -> bool {
+        matches!(self.status_code, Some(status_code) if status_code < 500)
+    }
+}
+
+/// A test helper sending a synthetic request to every route of a router,
+/// to catch handlers that panic or fail before deploying.
+///
+/// Path params are replaced with `placeholder`, `1` by default.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[tokio::test]
+/// async fn all_routes_respond() {
+///     let checks = TestHarness::new(router()).run().await;
+///     assert!(checks.iter().all(RouteCheck::is_ok), "{:?}", checks);
+/// }
+/// ```
+pub struct TestHarness {
+    router: Router,
+    placeholder: String,
+}
+
+impl TestHarness {
+    /// Create a harness for all routes registered on `router`.
+    pub fn new(router: Router) -> Self {
+        Self {
+            router,
+            placeholder: String::from("1"),
+        }
+    }
+
+    /// Consumes the harness, set the value used for path params and returns changed harness
+    pub fn placeholder(mut self, placeholder: &str) -> Self {
+        self.placeholder = placeholder.to_string();
+        self
+    }
+
+    /// Send one request per route, in registration order, through `HttpServe::serve`.
+    pub async fn run(&self) -> Vec<RouteCheck> {
+        let mut checks = Vec::new();
+        for (method, path) in self.router.routes() {
+            let url = self.fill_params(path);
+            let mut app = HttpServe::new_with_router(self.router.clone(), "http_request_update");
+            // Handler panics are turned into a 500 by `HttpServe`, record them before that
+            let handler_panic = Arc::new(Mutex::new(None));
+            let recorded = handler_panic.clone();
+            app.on_panic(move |message| {
+                *recorded.lock().unwrap() = Some(message.to_string());
+                HttpServe::internal_server_error().unwrap_err()
+            });
+            let req = RawHttpRequest {
+                method: method.to_string(),
+                url: url.clone(),
+                headers: Vec::new(),
+                body: Vec::new(),
+                certificate_version: None,
+            };
+            let (status_code, panic) = match CatchUnwind(Box::pin(app.serve(req))).await {
+                Ok(res) => match handler_panic.lock().unwrap().take() {
+                    Some(panic) => (None, Some(panic)),
+                    None => (Some(res.status_code), None),
+                },
+                Err(panic) => (None, Some(panic)),
+            };
+            checks.push(RouteCheck {
+                method: method.to_string(),
+                path: path.to_string(),
+                url,
+                status_code,
+                panic,
+            });
+        }
+        checks
+    }
+
+    /// Replace every `{param}` and `{*param}` segment with the placeholder.
+    fn fill_params(&self, path: &str) -> String {
+        let url = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') && segment.ends_with('}') {
+                    self.placeholder.as_str()
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        if url.is_empty() {
+            return String::from("/");
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{HeaderMap, HttpRequest, HttpResponse};
+    use serde_json::json;
+
+    async fn ok(_req: HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        Ok(HttpResponse {
+            status_code: 200,
+            headers: HeaderMap::new(),
+            body: json!({}).into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_all_ok() {
+        let mut router = Router::new();
+        router.get("/", false, ok);
+        router.get("/users/{id}", false, ok);
+        router.post("/files/{*path}", false, |req: HttpRequest| async move {
+            // Validation errors of the synthetic request are not failures
+            let _: serde_json::Value = req.body_into_struct()?;
+            ok(req).await
+        });
+
+        let checks = TestHarness::new(router).placeholder("42").run().await;
+        let urls: Vec<&str> = checks.iter().map(|check| check.url.as_str()).collect();
+        assert_eq!(urls, vec!["/", "/users/42", "/files/42"]);
+        assert!(checks.iter().all(RouteCheck::is_ok));
+        assert_eq!(checks[2].status_code, Some(400));
+    }
+
+    #[tokio::test]
+    async fn test_detect_failures() {
+        let mut router = Router::new();
+        router.get("/ok", false, ok);
+        router.get("/error", false, |_req: HttpRequest| async move {
+            Err(HttpServe::internal_server_error().unwrap_err())
+        });
+        router.get("/panic", false, |req: HttpRequest| async move {
+            if req.path == "/panic" {
+                panic!("handler exploded");
+            }
+            ok(req).await
+        });
+
+        let checks = TestHarness::new(router).run().await;
+        let failed: Vec<(&str, Option<u16>, Option<&str>)> = checks
+            .iter()
+            .filter(|check| !check.is_ok())
+            .map(|check| {
+                (
+                    check.path.as_str(),
+                    check.status_code,
+                    check.panic.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("/error", Some(500), None),
+                ("/panic", None, Some("handler exploded")),
+            ]
+        );
+    }
+}
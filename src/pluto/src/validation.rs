@@ -0,0 +1,115 @@
+use serde_json::{json, Value};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+use crate::http::{HeaderMap, HttpResponse};
+
+/// A single failed validation rule of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    /// The path of the field, e.g. `email`, `address.city` or `items[0].name`
+    pub field: String,
+    /// The code of the failed rule, e.g. `email` or `range`
+    pub code: String,
+    pub message: String,
+}
+
+/// Flatten `validator` errors into one entry per failed rule, sorted by field.
+/// Errors of nested structs and lists are reported with their full path.
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = Vec::new();
+    collect(errors, "", &mut fields);
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, fields: &mut Vec<FieldError>) {
+    for (name, kind) in errors.errors() {
+        let path = match prefix {
+            "" => name.to_string(),
+            _ => format!("{}.{}", prefix, name),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                for error in errors {
+                    fields.push(FieldError {
+                        field: path.clone(),
+                        code: error.code.to_string(),
+                        message: match error.message {
+                            Some(ref message) => message.to_string(),
+                            None => format!("failed the '{}' validation", error.code),
+                        },
+                    });
+                }
+            }
+            ValidationErrorsKind::Struct(errors) => collect(errors, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect(errors, &format!("{}[{}]", path, index), fields);
+                }
+            }
+        }
+    }
+}
+
+/// Build the 400 Bad Request response listing every failed rule as `{ field, code, message }`,
+/// so frontends can map the errors to form fields.
+///
+/// # Examples
+///
+/// ```ignore
+/// let body: SignUp = req.body_into_struct()?;
+/// body.validate().map_err(validation_error)?;
+/// ```
+pub fn validation_error(errors: ValidationErrors) -> HttpResponse {
+    let errors: Vec<Value> = field_errors(&errors)
+        .into_iter()
+        .map(|error| json!({ "field": error.field, "code": error.code, "message": error.message }))
+        .collect();
+    HttpResponse {
+        status_code: 400,
+        headers: HeaderMap::new(),
+        body: json!({
+            "statusCode": 400,
+            "message": "Validation failed",
+            "error": "Bad Request",
+            "errors": errors,
+        })
+        .into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::borrow::Cow;
+    use validator::ValidationError;
+
+    #[test]
+    fn test_validation_error() {
+        let mut address = ValidationErrors::new();
+        address.add("city", ValidationError::new("length"));
+        let mut errors = ValidationErrors::new();
+        errors.add("email", ValidationError::new("email"));
+        let mut age = ValidationError::new("range");
+        age.message = Some(Cow::from("must be between 18 and 20"));
+        errors.add("age", age);
+        let errors = ValidationErrors::merge(Err(errors), "address", Err(address)).unwrap_err();
+
+        let res = validation_error(errors);
+        assert_eq!(res.status_code, 400);
+        assert_eq!(
+            res.body,
+            json!({
+                "statusCode": 400,
+                "message": "Validation failed",
+                "error": "Bad Request",
+                "errors": [
+                    { "field": "address.city", "code": "length", "message": "failed the 'length' validation" },
+                    { "field": "age", "code": "range", "message": "must be between 18 and 20" },
+                    { "field": "email", "code": "email", "message": "failed the 'email' validation" },
+                ],
+            })
+            .into()
+        );
+    }
+}
@@ -9,15 +9,84 @@ macro_rules! render_view {
         $view:path
         $(, $arg:expr)*
     ) => {
-        let mut headers = HashMap::from([
+        let headers = $crate::http::HeaderMap::from([
             ("Content-Type".to_string(), "text/html".to_string()),
         ]);
         let mut buffer: Vec<u8> = Vec::new();
         $view(&mut buffer$(, $arg)*).unwrap();
-        return Ok(ic_pluto::http::HttpResponse {
+        return Ok($crate::http::HttpResponse {
             status_code: 200,
             headers,
-            body: ic_pluto::http::HttpBody::String(String::from_utf8(buffer).unwrap()),
+            body: $crate::http::HttpBody::String(String::from_utf8(buffer).unwrap()),
         })
     };
 }
+
+/// A variant of `render_view!` for large pages.
+///
+/// The template output is written straight into the response body as raw bytes,
+/// skipping the copy and the UTF-8 validation of the intermediate `String`.
+/// A failing template results in a 500 response instead of a panic.
+///
+/// # Example
+///
+/// ```ignore
+/// router.get("/", false, |_req: HttpRequest| async move {
+///     render_view_stream!(crate::compiled::templates::index_html);
+/// });
+/// ```
+#[macro_export]
+macro_rules! render_view_stream {
+    (
+        $view:path
+        $(, $arg:expr)*
+    ) => {
+        let headers = $crate::http::HeaderMap::from([
+            ("Content-Type".to_string(), "text/html".to_string()),
+        ]);
+        let mut buffer: Vec<u8> = Vec::new();
+        if $view(&mut buffer$(, $arg)*).is_err() {
+            return Err($crate::http::HttpServe::internal_server_error().unwrap_err());
+        }
+        return Ok($crate::http::HttpResponse {
+            status_code: 200,
+            headers,
+            body: $crate::http::HttpBody::Raw(buffer),
+        })
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::{HttpBody, HttpResponse};
+    use std::io::{Result, Write};
+
+    fn greeting(out: &mut impl Write, name: &str) -> Result<()> {
+        write!(out, "<h1>Hello {}!</h1>", name)
+    }
+
+    fn buffered(name: &str) -> std::result::Result<HttpResponse, HttpResponse> {
+        render_view!(greeting, name);
+    }
+
+    fn streamed(name: &str) -> std::result::Result<HttpResponse, HttpResponse> {
+        render_view_stream!(greeting, name);
+    }
+
+    #[test]
+    fn test_stream_matches_buffered() {
+        let buffered = buffered("Pluto").unwrap();
+        let streamed = streamed("Pluto").unwrap();
+
+        assert_eq!(streamed.status_code, 200);
+        assert_eq!(streamed.headers, buffered.headers);
+        assert_eq!(
+            streamed.body,
+            HttpBody::Raw(b"<h1>Hello Pluto!</h1>".to_vec())
+        );
+        assert_eq!(
+            Vec::<u8>::from(streamed.body),
+            Vec::<u8>::from(buffered.body)
+        );
+    }
+}